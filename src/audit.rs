@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use sha2::{Digest, Sha256};
+
+use secret::{Secret, SecretInfo, SecretType};
+
+/// Seconds in one day
+const DAY: i64 = 86400;
+
+/// Which entries are reported by audit
+///
+/// min_entropy - entries with lower estimated entropy (bits) are weak
+/// max_age_days - entries with value older than this are old
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AuditPolicy {
+    pub min_entropy: f64,
+    pub max_age_days: i64,
+}
+
+impl Default for AuditPolicy {
+    fn default() -> AuditPolicy {
+        AuditPolicy {
+            min_entropy: 50.0,
+            max_age_days: 365,
+        }
+    }
+}
+
+/// Problem of secret found by audit
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Finding {
+    Weak { entropy: f64 },
+    /// Ids of secrets with the same value
+    Reused { with: Vec<String> },
+    /// Days since value was set
+    Old { age_days: i64 },
+    /// Number of breaches with this password
+    Breached { count: u64 },
+}
+
+/// Secret with its findings (without value)
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub secret: SecretInfo,
+    pub findings: Vec<Finding>,
+}
+
+/// Audit of secrets. Values are checked one by one and
+/// only their hashes are kept for finding reused ones.
+pub struct Audit {
+    policy: AuditPolicy,
+    now: i64,
+    entries: Vec<AuditEntry>,
+    /// Value hash -> indexes of entries
+    values: HashMap<Vec<u8>, Vec<usize>>,
+}
+
+impl Audit {
+    pub fn new(policy: AuditPolicy, now: i64) -> Audit {
+        Audit {
+            policy: policy,
+            now: now,
+            entries: vec![],
+            values: HashMap::new(),
+        }
+    }
+
+    /// Check secret with its decrypted value
+    pub fn add(&mut self, secret: &Secret, value: &[u8]) {
+        let mut findings = vec![];
+
+        // Only text values are passwords
+        if secret.secret_type == SecretType::Text {
+            let entropy = estimate_entropy(value);
+            if entropy < self.policy.min_entropy {
+                findings.push(Finding::Weak { entropy: entropy });
+            }
+
+            let mut hasher = Sha256::new();
+            hasher.input(value);
+            self.values
+                .entry(hasher.result().to_vec())
+                .or_insert_with(Vec::new)
+                .push(self.entries.len());
+        }
+
+        // Time since value was set
+        let age_days = (self.now - secret.value_date()).max(0) / DAY;
+        if age_days > self.policy.max_age_days {
+            findings.push(Finding::Old { age_days: age_days });
+        }
+
+        self.entries.push(AuditEntry {
+            secret: secret.into(),
+            findings: findings,
+        });
+    }
+
+    /// Add finding to entry of secret
+    pub fn add_finding(&mut self, secret_id: &str, finding: Finding) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.secret.id == secret_id) {
+            entry.findings.push(finding);
+        }
+    }
+
+    /// Get entries with findings
+    pub fn finish(mut self) -> Vec<AuditEntry> {
+        for indexes in self.values.values() {
+            if indexes.len() < 2 {
+                continue;
+            }
+            for i in indexes.iter() {
+                let with = indexes
+                    .iter()
+                    .filter(|j| *j != i)
+                    .map(|j| self.entries[*j].secret.id.clone())
+                    .collect();
+                self.entries[*i].findings.push(Finding::Reused { with: with });
+            }
+        }
+
+        self.entries.retain(|e| !e.findings.is_empty());
+        self.entries.sort_by(|a, b| a.secret.name.cmp(&b.secret.name));
+        self.entries
+    }
+}
+
+/// Estimate entropy (bits) of password by its chars pool.
+/// Repeated chars and sequences (aaa, abc, 321) add only
+/// one bit per char.
+pub fn estimate_entropy(value: &[u8]) -> f64 {
+    let value = String::from_utf8_lossy(value);
+    let chars: Vec<char> = value.chars().collect();
+
+    let mut pool = 0;
+    if chars.iter().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if chars.iter().any(|c| c.is_ascii_punctuation() || *c == ' ') {
+        pool += 33;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        pool += 100;
+    }
+    if pool == 0 {
+        return 0.0;
+    }
+
+    let char_bits = (pool as f64).log2();
+    let mut entropy = 0.0;
+    for (i, c) in chars.iter().enumerate() {
+        let predictable = i > 0 && {
+            let diff = *c as i64 - chars[i - 1] as i64;
+            diff.abs() <= 1
+        };
+        entropy += match predictable {
+            true => 1.0,
+            false => char_bits,
+        };
+    }
+
+    entropy
+}
+
+#[cfg(test)]
+mod tests {
+    use audit::*;
+    use secret::{self, Secret, SecretType};
+
+    fn test_secret(name: &str, secret_type: SecretType, date: i64) -> Secret {
+        let mut secret = secret::test_secret(name);
+        secret.secret_type = secret_type;
+        secret.date = date;
+        secret
+    }
+
+    #[test]
+    fn entropy() {
+        assert_eq!(estimate_entropy(b""), 0.0);
+        assert!(estimate_entropy(b"aaaaaaaaaaaa") < 20.0);
+        assert!(estimate_entropy(b"abcdef123456") < 30.0);
+        assert!(estimate_entropy(b"password") < 50.0);
+        assert!(estimate_entropy(b"Xk9#mQ2$vL7@pR4!") > 90.0);
+    }
+
+    #[test]
+    fn findings() {
+        let now = 1000 * DAY;
+        let mut audit = Audit::new(AuditPolicy::default(), now);
+        let strong = b"Xk9#mQ2$vL7@pR4!".to_vec();
+
+        let a = test_secret("A", SecretType::Text, now);
+        let b = test_secret("B", SecretType::Text, now - 400 * DAY);
+        let c = test_secret("C", SecretType::Text, now);
+        let d = test_secret("D", SecretType::File, now);
+        audit.add(&a, &strong);
+        audit.add(&b, &strong);
+        audit.add(&c, b"qwerty");
+        audit.add(&d, b"qwerty");
+        let entries = audit.finish();
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].secret.name, "A".to_string());
+        assert_eq!(entries[0].findings, vec![Finding::Reused { with: vec![b.id.clone()] }]);
+        assert_eq!(entries[1].findings.len(), 2);
+        assert!(entries[1].findings.contains(&Finding::Old { age_days: 400 }));
+        match entries[2].findings[0] {
+            Finding::Weak { .. } => (),
+            ref f => panic!("Unexpected finding: {:?}", f),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::prelude::*;
+use std::path::Path;
+
+use chrono::{Datelike, NaiveDateTime, Utc};
+
+use errors::Error;
+use utils;
+
+/// Prefix of backup file names
+static PREFIX: &str = "pasd_backup_";
+
+/// Timestamp format of backup file names (UTC)
+static DATE_FMT: &str = "%Y%m%d%H%M%S%3f";
+
+/// Backup file info
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub name: String,
+    pub date: i64,
+    pub size: u64,
+}
+
+/// Which backups should be kept after saving
+///
+/// keep_last - number of latest backups
+/// keep_daily - number of days with one (latest) backup per day
+/// keep_weekly - number of weeks with one (latest) backup per week
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    pub keep_last: usize,
+    pub keep_daily: usize,
+    pub keep_weekly: usize,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> RetentionPolicy {
+        RetentionPolicy {
+            keep_last: 10,
+            keep_daily: 7,
+            keep_weekly: 4,
+        }
+    }
+}
+
+/// Write new timestamped backup and return its name
+pub fn create(dir: &Path, content: &[u8]) -> Result<String, Error> {
+    if !dir.exists() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let name = format!("{}{}", PREFIX, Utc::now().format(DATE_FMT));
+    utils::write_atomic(&dir.join(&name), |file| {
+        file.write_all(content)?;
+        Ok(())
+    })?;
+
+    Ok(name)
+}
+
+/// List backups, newest first
+pub fn list(dir: &Path) -> Result<Vec<BackupInfo>, Error> {
+    let mut backups = vec![];
+    if !dir.exists() {
+        return Ok(backups);
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let date = match parse_date(&name) {
+            Some(d) => d,
+            None => continue,
+        };
+
+        backups.push(BackupInfo {
+            name: name,
+            date: date.timestamp(),
+            size: entry.metadata()?.len(),
+        });
+    }
+
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(backups)
+}
+
+/// Read content of backup
+pub fn read(dir: &Path, name: &str) -> Result<Vec<u8>, Error> {
+    if parse_date(name).is_none() {
+        return Err(Error::NotFound);
+    }
+
+    match fs::read(dir.join(name)) {
+        Ok(content) => Ok(content),
+        Err(ref e) if e.kind() == ::std::io::ErrorKind::NotFound => Err(Error::NotFound),
+        Err(e) => Err(Error::IO(e)),
+    }
+}
+
+/// Remove backups not covered by retention policy
+/// and return names of removed ones
+pub fn prune(dir: &Path, policy: &RetentionPolicy) -> Result<Vec<String>, Error> {
+    let backups = list(dir)?;
+    let keep = retained(&backups, policy);
+
+    let mut removed = vec![];
+    for backup in backups {
+        if !keep.contains(&backup.name) {
+            fs::remove_file(dir.join(&backup.name))?;
+            removed.push(backup.name);
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Get names of backups (sorted newest first)
+/// that should be kept
+fn retained(backups: &[BackupInfo], policy: &RetentionPolicy) -> HashSet<String> {
+    let mut keep = HashSet::new();
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+
+    for (i, backup) in backups.iter().enumerate() {
+        if i < policy.keep_last {
+            keep.insert(backup.name.clone());
+        }
+
+        let date = NaiveDateTime::from_timestamp(backup.date, 0).date();
+
+        // Latest backup of a day
+        if days.len() < policy.keep_daily && days.insert(date) {
+            keep.insert(backup.name.clone());
+        }
+
+        // Latest backup of a week
+        let week = (date.iso_week().year(), date.iso_week().week());
+        if weeks.len() < policy.keep_weekly && weeks.insert(week) {
+            keep.insert(backup.name.clone());
+        }
+    }
+
+    keep
+}
+
+/// Get date of backup from its name
+fn parse_date(name: &str) -> Option<NaiveDateTime> {
+    if !name.starts_with(PREFIX) {
+        return None;
+    }
+    NaiveDateTime::parse_from_str(&name[PREFIX.len()..], DATE_FMT).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use backup::*;
+    use utils;
+
+    fn backup_at(date: &str) -> BackupInfo {
+        let name = format!("{}{}", PREFIX, date);
+        BackupInfo {
+            date: parse_date(&name).unwrap().timestamp(),
+            name: name,
+            size: 0,
+        }
+    }
+
+    #[test]
+    fn creating_and_reading() {
+        let dir = env::temp_dir().join(format!("pasd_backups_{}", utils::uid()));
+        let name = create(&dir, b"content").unwrap();
+
+        let backups = list(&dir).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].name, name);
+        assert_eq!(backups[0].size, 7);
+        assert_eq!(read(&dir, &name).unwrap(), Vec::from("content"));
+
+        let mode = fs::metadata(dir.join(&name)).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // Only backup names are allowed
+        assert!(read(&dir, "../etc/passwd").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn thinning() {
+        // Newest first
+        let backups = vec![
+            backup_at("20190310120000000"),
+            backup_at("20190310110000000"),
+            backup_at("20190310100000000"),
+            backup_at("20190309100000000"),
+            backup_at("20190308100000000"),
+            backup_at("20190301100000000"),
+            backup_at("20190220100000000"),
+        ];
+        let policy = RetentionPolicy {
+            keep_last: 2,
+            keep_daily: 2,
+            keep_weekly: 3,
+        };
+        let keep = retained(&backups, &policy);
+
+        // Last two, latest of 10th and 9th days,
+        // latest of three weeks
+        assert!(keep.contains(&backups[0].name));
+        assert!(keep.contains(&backups[1].name));
+        assert!(!keep.contains(&backups[2].name));
+        assert!(keep.contains(&backups[3].name));
+        assert!(keep.contains(&backups[4].name));
+        assert!(keep.contains(&backups[5].name));
+        assert!(!keep.contains(&backups[6].name));
+    }
+}
@@ -0,0 +1,139 @@
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{prelude::*, BufReader, SeekFrom};
+use std::path::Path;
+
+use sha1::{Digest, Sha1};
+
+use errors::Error;
+
+/// Length of hex SHA-1 hash
+const HASH_LEN: usize = 40;
+
+/// Dataset of breached passwords
+///
+/// File is sorted by hash (HIBP "ordered by hash" format),
+/// one "SHA1HEX:COUNT" per line. Count is optional.
+/// Lookups use binary search over byte offsets, so only
+/// a few lines are read for every check.
+pub struct BreachFile {
+    file: File,
+    len: u64,
+}
+
+impl BreachFile {
+    pub fn open(path: &Path) -> Result<BreachFile, Error> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+
+        Ok(BreachFile {
+            file: file,
+            len: len,
+        })
+    }
+
+    /// Get number of breaches of password (None if not found)
+    pub fn count(&mut self, value: &[u8]) -> Result<Option<u64>, Error> {
+        let mut hasher = Sha1::new();
+        hasher.input(value);
+        let hash: String = hasher
+            .result()
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+
+        self.find(&hash)
+    }
+
+    /// Find uppercase hex hash. Lines starting in [lo, hi)
+    /// are left to check.
+    fn find(&mut self, hash: &str) -> Result<Option<u64>, Error> {
+        let (mut lo, mut hi) = (0, self.len);
+        let mut line = String::with_capacity(64);
+
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+
+            // First line starting at or after mid
+            let mut reader = BufReader::new(&mut self.file);
+            let start = match mid {
+                0 => {
+                    reader.seek(SeekFrom::Start(0))?;
+                    0
+                }
+                _ => {
+                    reader.seek(SeekFrom::Start(mid - 1))?;
+                    line.clear();
+                    mid - 1 + reader.read_line(&mut line)? as u64
+                }
+            };
+            if start >= hi {
+                hi = mid;
+                continue;
+            }
+
+            line.clear();
+            let line_len = reader.read_line(&mut line)? as u64;
+            if line_len == 0 {
+                hi = mid;
+                continue;
+            }
+            let (line_hash, count) = parse_line(&line);
+
+            match line_hash.to_uppercase().as_str().cmp(hash) {
+                Ordering::Equal => return Ok(Some(count)),
+                Ordering::Less => lo = start + line_len,
+                Ordering::Greater => hi = mid,
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Get hash and count of line
+fn parse_line(line: &str) -> (&str, u64) {
+    let line = line.trim();
+    match line.find(':') {
+        Some(i) => (&line[..i], line[i + 1..].parse().unwrap_or(1)),
+        None => (&line[..line.len().min(HASH_LEN)], 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use breach::*;
+    use utils;
+
+    #[test]
+    fn lookup() {
+        let path = env::temp_dir().join(format!("pasd_breach_{}", utils::uid()));
+        // sha1("password"), sha1("123456"), sha1("qwerty") sorted
+        // with some other hashes around
+        let content = "\
+            0000000000000000000000000000000000000000:1\n\
+            5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\n\
+            7C4A8D09CA3762AF61E59520943DC26494F8941B:37359195\n\
+            B1B3773A05C0ED0176787A4F1574FF0075F7521E\n\
+            FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF:7\n";
+        fs::write(&path, content).unwrap();
+
+        let mut breaches = BreachFile::open(&path).unwrap();
+        assert_eq!(breaches.count(b"password").unwrap(), Some(3861493));
+        assert_eq!(breaches.count(b"123456").unwrap(), Some(37359195));
+        assert_eq!(breaches.count(b"qwerty").unwrap(), Some(1));
+        assert_eq!(breaches.count(b"correct horse battery staple").unwrap(), None);
+        assert_eq!(breaches.find(&"0".repeat(40)).unwrap(), Some(1));
+        assert_eq!(breaches.find(&"F".repeat(40)).unwrap(), Some(7));
+
+        // Empty dataset
+        fs::write(&path, "").unwrap();
+        let mut breaches = BreachFile::open(&path).unwrap();
+        assert_eq!(breaches.count(b"password").unwrap(), None);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
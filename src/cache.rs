@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+
+use header::KdfParams;
+use key::Key;
+use secret::Secret;
+
+/// Files modified within this time (seconds) are not cached:
+/// later change may get the same mtime due to its granularity
+const RACY_SECS: i64 = 2;
+
+/// Identity of database file content. Changes when file
+/// is replaced (device, inode) or rewritten (mtime, size).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FileStamp {
+    dev: u64,
+    ino: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+    len: u64,
+}
+
+impl FileStamp {
+    pub fn new(meta: &Metadata) -> FileStamp {
+        FileStamp {
+            dev: meta.dev(),
+            ino: meta.ino(),
+            mtime: meta.mtime(),
+            mtime_nsec: meta.mtime_nsec(),
+            len: meta.len(),
+        }
+    }
+
+    /// Check if file was modified too recently to rely on its stamp
+    pub fn is_racy(&self, now: i64) -> bool {
+        now - self.mtime < RACY_SECS
+    }
+}
+
+/// Decrypted content of database file
+#[derive(Debug)]
+pub struct Content {
+    pub outer_kdf: KdfParams,
+    pub inner_kdf: KdfParams,
+    pub keys: Vec<Key>,
+    pub secrets: Vec<Secret>,
+    pub folder_expiry: HashMap<String, u32>,
+    /// Hash and raw content of file
+    pub hash: Vec<u8>,
+    pub base: Vec<u8>,
+}
+
+/// Decrypted database kept between requests. It's valid
+/// only while file has the same stamp.
+#[derive(Debug, Default)]
+pub struct DbCache {
+    entry: Option<(FileStamp, Content)>,
+}
+
+impl DbCache {
+    /// Take content if it was cached for this file stamp.
+    /// Cache is empty afterwards in any case.
+    pub fn take(&mut self, stamp: &FileStamp) -> Option<Content> {
+        match self.entry.take() {
+            Some((s, content)) if s == *stamp => Some(content),
+            _ => None,
+        }
+    }
+
+    /// Keep content of file with stamp, unless stamp is racy
+    pub fn put(&mut self, stamp: FileStamp, content: Content, now: i64) {
+        self.entry = match stamp.is_racy(now) {
+            true => None,
+            false => Some((stamp, content)),
+        };
+    }
+
+    pub fn clear(&mut self) {
+        self.entry = None;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entry.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs;
+
+    use cache::*;
+    use header::KdfParams;
+    use utils;
+
+    fn content() -> Content {
+        Content {
+            outer_kdf: KdfParams::default(),
+            inner_kdf: KdfParams::default(),
+            keys: vec![],
+            secrets: vec![],
+            folder_expiry: HashMap::new(),
+            hash: vec![],
+            base: vec![],
+        }
+    }
+
+    #[test]
+    fn stamps() {
+        let path = env::temp_dir().join(format!("pasd_cache_{}", utils::uid()));
+        fs::write(&path, "content").unwrap();
+        let stamp = FileStamp::new(&fs::metadata(&path).unwrap());
+        let mut cache = DbCache::default();
+
+        // Recently modified file
+        cache.put(stamp, content(), stamp.mtime);
+        assert!(cache.is_empty());
+
+        cache.put(stamp, content(), stamp.mtime + RACY_SECS);
+        assert!(cache.take(&stamp).is_some());
+        assert!(cache.take(&stamp).is_none());
+
+        // Replaced file
+        cache.put(stamp, content(), stamp.mtime + RACY_SECS);
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, "other content").unwrap();
+        let new_stamp = FileStamp::new(&fs::metadata(&path).unwrap());
+        assert!(new_stamp != stamp);
+        assert!(cache.take(&new_stamp).is_none());
+        assert!(cache.is_empty());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,19 +1,42 @@
+use audit::AuditPolicy;
+use backup::RetentionPolicy;
 use errors::Error;
+use header::KdfParams;
+use session::SessionPolicy;
 use serde::de::DeserializeOwned;
 use std::env;
 use std::fs::{self, File, OpenOptions};
 use std::io::{self, prelude::*};
 use std::os::unix::fs::OpenOptionsExt;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use toml;
 
 /// Main config struct
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
     pub db_path: Option<String>,
     pub db_key: Option<String>,
     pub ipc_socket_path: Option<String>,
     pub backups_path: Option<String>,
+    pub backups_keep_last: Option<usize>,
+    pub backups_keep_daily: Option<usize>,
+    pub backups_keep_weekly: Option<usize>,
+    pub scrypt_log2_n: Option<u8>,
+    pub scrypt_r: Option<u32>,
+    pub scrypt_p: Option<u32>,
+    pub wordlist_path: Option<String>,
+    pub audit_min_entropy: Option<f64>,
+    pub audit_max_age_days: Option<i64>,
+    pub breach_path: Option<String>,
+    pub public_suffix_path: Option<String>,
+    pub expiry_check_interval: Option<u64>,
+    pub session_idle_timeout: Option<u64>,
+    pub session_max_age: Option<u64>,
+    pub auto_lock_idle: Option<u64>,
+    pub lock_on_sleep: Option<bool>,
+    pub logind_bus_address: Option<String>,
+    pub read_only: Option<bool>,
 }
 
 impl Config {
@@ -25,12 +48,51 @@ impl Config {
         // Read it
         match Config::read(dir_path.to_owned(), "config.toml") {
             Ok(conf) => conf,
-            Err(_) => Config {
-                db_path: None,
-                db_key: None,
-                ipc_socket_path: None,
-                backups_path: None,
-            },
+            Err(_) => Config::default(),
+        }
+    }
+
+    /// Get scrypt params for new database saves
+    pub fn kdf_params(&self) -> KdfParams {
+        let default = KdfParams::default();
+        KdfParams {
+            log2_n: self.scrypt_log2_n.unwrap_or(default.log2_n),
+            r: self.scrypt_r.unwrap_or(default.r),
+            p: self.scrypt_p.unwrap_or(default.p),
+        }
+    }
+
+    /// Get retention policy of backups
+    pub fn retention_policy(&self) -> RetentionPolicy {
+        let default = RetentionPolicy::default();
+        RetentionPolicy {
+            keep_last: self.backups_keep_last.unwrap_or(default.keep_last),
+            keep_daily: self.backups_keep_daily.unwrap_or(default.keep_daily),
+            keep_weekly: self.backups_keep_weekly.unwrap_or(default.keep_weekly),
+        }
+    }
+
+    /// Get audit policy
+    pub fn audit_policy(&self) -> AuditPolicy {
+        let default = AuditPolicy::default();
+        AuditPolicy {
+            min_entropy: self.audit_min_entropy.unwrap_or(default.min_entropy),
+            max_age_days: self.audit_max_age_days.unwrap_or(default.max_age_days),
+        }
+    }
+
+    /// Get lifetime of unlocked sessions
+    pub fn session_policy(&self) -> SessionPolicy {
+        let default = SessionPolicy::default();
+        SessionPolicy {
+            idle: self
+                .session_idle_timeout
+                .map(Duration::from_secs)
+                .unwrap_or(default.idle),
+            absolute: self
+                .session_max_age
+                .map(Duration::from_secs)
+                .unwrap_or(default.absolute),
         }
     }
 
@@ -100,6 +162,60 @@ const DEFAULT: &'static str = "\
 # Path to ipc socket.
 # ipc_socket_path = \"/tmp/pasd.sock\"
 
+# Open database only for reading. Read-only daemons can
+# share database, other daemons need exclusive access.
+# read_only = false
+
 # Path to dir where will be stored backups.
 # backups_path = \"/path/to/backups/\"
+
+# How many backups to keep: last N saves, latest backup
+# of each of N days and latest backup of each of N weeks.
+# backups_keep_last = 10
+# backups_keep_daily = 7
+# backups_keep_weekly = 4
+
+# Scrypt cost parameters. Stronger values apply to existing
+# database after 'rekey-kdf' request.
+# scrypt_log2_n = 15
+# scrypt_r = 16
+# scrypt_p = 1
+
+# Diceware word list for generated passphrases
+# (one word per line). Built-in list is used by default.
+# wordlist_path = \"/path/to/wordlist.txt\"
+
+# Audit reports passwords with lower estimated entropy (bits)
+# and values that weren't changed for more days.
+# audit_min_entropy = 50.0
+# audit_max_age_days = 365
+
+# Dataset of breached passwords: file sorted by SHA-1 hash
+# with \"SHA1HEX:COUNT\" lines (HIBP ordered by hash format).
+# New passwords and audit are checked against it.
+# breach_path = \"/path/to/pwned-passwords-sha1-ordered-by-hash.txt\"
+
+# Public Suffix List for matching urls of secrets by domain
+# (example.co.uk for a.example.co.uk). Without it secrets
+# are matched only by host and subdomains.
+# public_suffix_path = \"/usr/share/publicsuffix/public_suffix_list.dat\"
+
+# How often (seconds) to check expiry of secrets and
+# notify subscribed clients.
+# expiry_check_interval = 3600
+
+# Sessions opened by 'unlock' expire after this many seconds
+# without requests and this many seconds after unlock.
+# session_idle_timeout = 300
+# session_max_age = 3600
+
+# Lock all sessions when none of them was used for this
+# many seconds (0 disables). Sessions are also locked on
+# SIGUSR1 and 'lock-all' request.
+# auto_lock_idle = 900
+
+# Lock all sessions on suspend and screen lock (logind).
+# Bus address may be set to use other bus than system one.
+# lock_on_sleep = true
+# logind_bus_address = \"unix:path=/run/dbus/system_bus_socket\"
 ";
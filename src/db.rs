@@ -1,33 +1,51 @@
-use std::collections::HashMap;
-use std::os::unix::fs::OpenOptionsExt;
-use std::fs::{self, File, OpenOptions};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
 use std::io::{self, prelude::*};
+use std::mem;
 use std::path::Path;
+use std::time::{Duration, Instant};
 
-use block_cipher_trait::generic_array::typenum::consts::U16;
+use aes_gcm::aead::{Aead, Payload};
+use block_cipher_trait::generic_array::typenum::consts::{U12, U16};
 use block_cipher_trait::generic_array::GenericArray;
 use block_modes::BlockMode;
+use chrono::Local;
 use msgpack;
-use scrypt::{scrypt, ScryptParams};
+use scrypt::scrypt;
 use sha2::{Digest, Sha256};
 
+use audit::{Audit, AuditEntry, AuditPolicy, Finding};
+use backup::{self, BackupInfo, RetentionPolicy};
+use breach::BreachFile;
+use cache::{Content, DbCache, FileStamp};
 use config::Config;
 use errors::Error;
-use key::{Key, KeyInfo, KeyType, Passwords};
-use secret::{AddSecretArgs, Secret, SecretInfo, SecretType};
-use utils::Aes256Cbc;
-
-static DB_VERSION: u8 = 0x00;
-
-// static OUTER_SCRYPT_LOG2_N: u8 = 15;
-// static OUTER_SCRYPT_R: u32 = 16;
-// static INNER_SCRYPT_LOG2_N: u8 = 15;
-// static INNER_SCRYPT_R: u32 = 16;
-
-static OUTER_SCRYPT_LOG2_N: u8 = 1;
-static OUTER_SCRYPT_R: u32 = 1;
-static INNER_SCRYPT_LOG2_N: u8 = 1;
-static INNER_SCRYPT_R: u32 = 1;
+use expiry::{self, ExpiringSecret};
+use header::{Cipher, Header, KdfParams, DB_VERSION};
+use key::{GroupKey, Key, KeyInfo, KeyType, Passwords};
+use lock::DbLock;
+use merge::{self, Conflict, State};
+use migrations;
+use notify::{self, Notifier};
+use otp::{self, OtpCode};
+use password::{self, Password, PasswordArgs};
+use query::Query;
+use search::{self, SearchResult};
+use url_match::{self, SuffixList};
+use secure::SecretBuf;
+use session::{CachedKey, Sessions};
+use secret::{
+    has_encrypted, normalize_folder, AddSecretArgs, FieldValue, HistoryValue, Secret, SecretInfo, SecretType,
+    SecretsFilter, UpdateSecretArgs,
+};
+use utils::{self, Aes256Cbc};
+
+/// Secret matched with origin url
+#[derive(Debug, Clone, Serialize)]
+pub struct UrlCandidate {
+    pub secret: SecretInfo,
+    pub strength: u8,
+}
 
 /// Central struct that keeps secrets along with
 /// some additional info.
@@ -39,56 +57,211 @@ pub struct DB {
     key: Option<String>,
     #[serde(skip)]
     backups_path: Option<String>,
+    #[serde(skip)]
+    retention: RetentionPolicy,
+    #[serde(skip)]
+    wordlist_path: Option<String>,
+    #[serde(skip)]
+    audit: AuditPolicy,
+    #[serde(skip)]
+    breach_path: Option<String>,
+    #[serde(skip)]
+    public_suffix_path: Option<String>,
+    #[serde(skip)]
+    read_only: bool,
+    /// Lock of database file held by this process
+    #[serde(skip)]
+    lock: Option<DbLock>,
+    #[serde(skip)]
+    kdf: KdfParams,
+    #[serde(skip)]
+    outer_kdf: KdfParams,
+    #[serde(skip)]
+    inner_kdf: KdfParams,
+    /// Stamp of file with loaded content
+    #[serde(skip)]
+    stamp: Option<FileStamp>,
+    /// Decrypted content between requests
+    #[serde(skip)]
+    cache: DbCache,
+    /// Hash of file content at load
+    #[serde(skip)]
+    hash: Option<Vec<u8>>,
+    /// Raw file content at load (base of merge)
+    #[serde(skip)]
+    base: Option<Vec<u8>>,
+    /// Subscribers of events (not stored)
+    #[serde(skip)]
+    pub notifier: Notifier,
+    /// Unlocked sessions (not stored)
+    #[serde(skip)]
+    sessions: Sessions,
+    /// Group keys of session of current request
+    #[serde(skip)]
+    session_keys: Vec<CachedKey>,
+    /// Ids of keys validated by session of current request
+    #[serde(skip)]
+    session_keys_ids: Vec<String>,
     pub keys: Vec<Key>,
     pub secrets: Vec<Secret>,
+    /// Folder -> rotation period in days
+    #[serde(default)]
+    pub folder_expiry: HashMap<String, u32>,
+    /// Secret id -> time of last use. Uses are recorded
+    /// in memory and stored with the next save.
+    #[serde(default)]
+    used: HashMap<String, i64>,
 }
 
 impl DB {
     /// Initialize database.
     pub fn new(conf: &Config) -> DB {
+        let kdf = conf.kdf_params();
         DB {
             path: conf.db_path.clone(),
             key: conf.db_key.clone(),
             backups_path: conf.backups_path.clone(),
+            retention: conf.retention_policy(),
+            wordlist_path: conf.wordlist_path.clone(),
+            audit: conf.audit_policy(),
+            breach_path: conf.breach_path.clone(),
+            public_suffix_path: conf.public_suffix_path.clone(),
+            read_only: conf.read_only.unwrap_or(false),
+            lock: None,
+            kdf: kdf,
+            outer_kdf: kdf,
+            inner_kdf: kdf,
+            stamp: None,
+            cache: DbCache::default(),
+            hash: None,
+            base: None,
+            notifier: Notifier::default(),
+            sessions: Sessions::new(conf.session_policy()),
+            session_keys: vec![],
+            session_keys_ids: vec![],
             keys: vec![],
             secrets: vec![],
+            folder_expiry: HashMap::new(),
+            used: HashMap::new(),
         }
     }
 
     /// Load secure data
     pub fn load(&mut self) -> Result<(), Error> {
         let mut db_file = self.open("r")?;
-        if let Ok(meta) = db_file.metadata() {
-            if meta.len() == 0 {
-                return Ok(());
-            }
+        let meta = db_file.metadata()?;
+        if meta.len() == 0 {
+            // New database uses configured kdf params
+            self.outer_kdf = self.kdf;
+            self.inner_kdf = self.kdf;
+            self.hash = Some(content_hash(&[]));
+            self.base = Some(vec![]);
+            return Ok(());
         }
 
-        // Read and validate file
-        let mut sig = [0u8; 3];
-        let mut data = Vec::with_capacity(1024);
-        db_file.read(&mut sig)?;
-        if sig[0] != 0x00 || sig[1] != sig[2] {
-            return Err(Error::InvalidDBFormat);
-        }
-        if sig[1] != DB_VERSION {
-            // Handle format change
-            return Err(Error::InvalidDBFormat);
+        // Use cached content if file wasn't changed since
+        let stamp = FileStamp::new(&meta);
+        if let Some(content) = self.cache.take(&stamp) {
+            self.outer_kdf = content.outer_kdf;
+            self.inner_kdf = content.inner_kdf;
+            self.keys = content.keys;
+            self.secrets = content.secrets;
+            self.folder_expiry = content.folder_expiry;
+            self.hash = Some(content.hash);
+            self.base = Some(content.base);
+            self.stamp = Some(stamp);
+            return Ok(());
         }
-        db_file.read_to_end(&mut data)?;
 
-        // Decrypt
-        let outer_key = self.get_outer_key()?;
-        let outer_iv = self.get_outer_iv()?;
-        let cipher = match Aes256Cbc::new_var(&outer_key, &outer_iv) {
-            Ok(c) => c,
-            Err(_) => return Err(Error::IncorrectOuterKey),
+        // Read and decrypt file
+        let mut content = Vec::with_capacity(1024);
+        db_file.read_to_end(&mut content)?;
+        let (header, decrypted) = self.decrypt_content(&content)?;
+        self.outer_kdf = header.outer_kdf;
+        self.inner_kdf = header.inner_kdf;
+
+        // Upgrade older format
+        let upgrade = header.version < DB_VERSION;
+        let decrypted = match upgrade {
+            true => {
+                if !self.read_only {
+                    self.backup_before_upgrade(header.version)?;
+                }
+                migrations::migrate(header.version, decrypted)?
+            }
+            false => decrypted,
         };
-        let decrypted = cipher.decrypt_vec(&data)?;
 
         let db: DB = msgpack::from_slice(&decrypted)?;
         self.keys = db.keys;
         self.secrets = db.secrets;
+        self.folder_expiry = db.folder_expiry;
+        merge::merge_used(&mut self.used, &db.used);
+        self.hash = Some(content_hash(&content));
+        self.base = Some(content);
+        self.stamp = Some(stamp);
+
+        // Rewrite upgraded database in current format
+        if upgrade && !self.read_only {
+            self.save()?;
+        }
+
+        Ok(())
+    }
+
+    /// Validate header of raw database content and decrypt it
+    fn decrypt_content(&self, content: &[u8]) -> Result<(Header, SecretBuf), Error> {
+        let mut content = content;
+        let header = Header::read(&mut content)?;
+        let data = content;
+
+        // Get outer key and nonce
+        let salt = match header.is_randomized() {
+            true => header.salt.clone(),
+            false => self.get_legacy_outer_salt()?,
+        };
+        let outer_key = self.get_outer_key(&salt, &header.outer_kdf)?;
+
+        // Decrypt
+        let decrypted = match header.cipher {
+            Cipher::Aes256Cbc => {
+                let outer_iv = self.get_legacy_outer_iv()?;
+                let cipher = match Aes256Cbc::new_var(&outer_key[..], &outer_iv) {
+                    Ok(c) => c,
+                    Err(_) => return Err(Error::IncorrectOuterKey),
+                };
+                SecretBuf::new(cipher.decrypt_vec(data)?)
+            }
+            Cipher::Aes256Gcm => {
+                let header_bytes = header.to_bytes();
+                let outer_nonce = match header.is_randomized() {
+                    true => *GenericArray::from_slice(&header.nonce),
+                    false => self.get_legacy_outer_nonce()?,
+                };
+                let cipher = utils::gcm_cipher(&outer_key);
+                let payload = Payload {
+                    msg: data,
+                    aad: &header_bytes,
+                };
+                // Wrong db key fails authentication the same way
+                match cipher.decrypt(&outer_nonce, payload) {
+                    Ok(d) => SecretBuf::new(d),
+                    Err(_) => return Err(Error::Tampered),
+                }
+            }
+        };
+
+        Ok((header, decrypted))
+    }
+
+    /// Copy database file before format upgrade
+    fn backup_before_upgrade(&self, version: u8) -> Result<(), Error> {
+        let path = match self.path {
+            Some(ref p) => p,
+            None => return Err(Error::IncorrectConf),
+        };
+        let backup_path = format!("{}.v{}.bak", path, version);
+        fs::copy(path, &backup_path)?;
 
         Ok(())
     }
@@ -101,53 +274,193 @@ impl DB {
         Ok(())
     }
 
-    /// Unload secure data
+    /// Unload secure data. While any session is unlocked
+    /// it's kept in cache until the file is changed.
     pub fn unload(&mut self) {
-        self.keys.clear();
-        self.secrets.clear();
+        let stamp = match self.sessions.is_active(Instant::now()) {
+            true => self.stamp.take(),
+            false => None,
+        };
+        self.stamp = None;
+        match stamp {
+            Some(stamp) => {
+                let content = Content {
+                    outer_kdf: self.outer_kdf,
+                    inner_kdf: self.inner_kdf,
+                    keys: mem::replace(&mut self.keys, vec![]),
+                    secrets: mem::replace(&mut self.secrets, vec![]),
+                    folder_expiry: mem::replace(&mut self.folder_expiry, HashMap::new()),
+                    hash: self.hash.take().unwrap_or_default(),
+                    base: self.base.take().unwrap_or_default(),
+                };
+                self.cache.put(stamp, content, Local::now().timestamp());
+            }
+            None => {
+                self.keys.clear();
+                self.secrets.clear();
+                self.folder_expiry.clear();
+                self.hash = None;
+                self.base = None;
+            }
+        }
     }
 
     /// Save db
     pub fn save(&mut self) -> Result<(), Error> {
-        let mut db_file = self.open("rw")?;
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        let path = match self.path {
+            Some(ref p) => p.clone(),
+            None => return Err(Error::IncorrectConf),
+        };
+        self.acquire_lock()?;
 
-        // Serialize DB
-        let data = msgpack::to_vec(&self)?;
+        // Merge changes made to file since load
+        self.merge_external(&path)?;
 
-        // Encrypt
-        let outer_key = self.get_outer_key()?;
-        let outer_iv = self.get_outer_iv()?;
-        let cipher = match Aes256Cbc::new_var(&outer_key, &outer_iv) {
-            Ok(c) => c,
-            Err(_) => return Err(Error::IncorrectOuterKey),
-        };
-        let encrypted = cipher.encrypt_vec(&data);
+        // Forget use of removed secrets
+        {
+            let ids: HashSet<&str> = self.secrets.iter().map(|s| s.id.as_str()).collect();
+            self.used.retain(|id, _| ids.contains(id.as_str()));
+        }
 
-        // Reset and write new content
-        let sig: [u8; 3] = [0x00, DB_VERSION, DB_VERSION];
-        db_file.set_len(0)?;
-        db_file.write_all(&sig)?;
-        db_file.write_all(&encrypted)?;
+        // Serialize DB into buffer that is zeroed on drop
+        let mut data = SecretBuf::with_capacity(64 * 1024);
+        msgpack::encode::write(&mut data, &self)?;
+
+        // Encrypt with fresh salt and nonce
+        let header = Header::new(self.outer_kdf, self.inner_kdf);
+        let header_bytes = header.to_bytes();
+        let outer_key = self.get_outer_key(&header.salt, &header.outer_kdf)?;
+        let outer_nonce = GenericArray::from_slice(&header.nonce);
+        let cipher = utils::gcm_cipher(&outer_key);
+        let payload = Payload {
+            msg: &data[..],
+            aad: &header_bytes,
+        };
+        let encrypted = match cipher.encrypt(outer_nonce, payload) {
+            Ok(e) => e,
+            Err(_) => return Err(Error::Internal),
+        };
+        let mut content = header_bytes;
+        content.extend_from_slice(&encrypted);
+
+        // Replace content atomically. Loaded content doesn't
+        // match any file until it's written.
+        self.stamp = None;
+        let mut stamp = None;
+        utils::write_atomic(Path::new(&path), |db_file| {
+            db_file.write_all(&content)?;
+            stamp = Some(FileStamp::new(&db_file.metadata()?));
+            Ok(())
+        })?;
+        self.stamp = stamp;
 
         // Backup
         if let Some(ref b) = self.backups_path {
-            let backup_name = "pasd_backup";
             let backup_dir = Path::new(b);
-            let mut backup_file = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .mode(0o666)
-                .open(backup_dir.join(backup_name))?;
-            backup_file.set_len(0)?;
-            backup_file.write_all(&sig)?;
-            backup_file.write_all(&encrypted)?;
+            backup::create(backup_dir, &content)?;
+            backup::prune(backup_dir, &self.retention)?;
+        }
+
+        // Written content is base of the next merge
+        self.hash = Some(content_hash(&content));
+        self.base = Some(content);
+
+        Ok(())
+    }
+
+    /// Merge changes made to database file since it was loaded
+    /// by other process. Changes that can't be merged are kept
+    /// in conflict copy of file.
+    fn merge_external(&mut self, path: &str) -> Result<(), Error> {
+        let (hash, base) = match (self.hash.take(), self.base.take()) {
+            (Some(h), Some(b)) => (h, b),
+            _ => return Ok(()),
+        };
+        let current = match fs::read(path) {
+            Ok(c) => c,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(Error::IO(e)),
+        };
+        if content_hash(&current) == hash {
+            return Ok(());
+        }
+
+        // Three-way merge
+        let base = self.decode(&base)?;
+        let theirs = self.decode(&current)?;
+        let ours = State {
+            outer_kdf: self.outer_kdf,
+            inner_kdf: self.inner_kdf,
+            keys: mem::replace(&mut self.keys, vec![]),
+            secrets: mem::replace(&mut self.secrets, vec![]),
+            folder_expiry: mem::replace(&mut self.folder_expiry, HashMap::new()),
+            used: mem::replace(&mut self.used, HashMap::new()),
+        };
+        let (state, conflict) = match merge::merge_states(&base, &ours, &theirs) {
+            Ok(merged) => (merged, None),
+            Err(conflict) => (ours, Some(conflict)),
+        };
+        self.outer_kdf = state.outer_kdf;
+        self.inner_kdf = state.inner_kdf;
+        self.keys = state.keys;
+        self.secrets = state.secrets;
+        self.folder_expiry = state.folder_expiry;
+        self.used = state.used;
+
+        // Keep our version, their one goes to copy
+        if let Some(conflict) = conflict {
+            let copy_path = format!(
+                "{}.conflict-{}",
+                path,
+                Local::now().format("%Y%m%d-%H%M%S")
+            );
+            utils::write_atomic(Path::new(&copy_path), |copy_file| {
+                copy_file.write_all(&current)?;
+                Ok(())
+            })?;
+            let item = match conflict {
+                Conflict::Keys => None,
+                Conflict::Item(id) => Some(id),
+            };
+            self.notifier.conflict(Path::new(&copy_path), item);
         }
 
         Ok(())
     }
 
+    /// Decrypt raw content of database file
+    fn decode(&self, content: &[u8]) -> Result<State, Error> {
+        if content.is_empty() {
+            return Ok(State {
+                outer_kdf: self.kdf,
+                inner_kdf: self.kdf,
+                keys: vec![],
+                secrets: vec![],
+                folder_expiry: HashMap::new(),
+                used: HashMap::new(),
+            });
+        }
+
+        let (header, decrypted) = self.decrypt_content(content)?;
+        let decrypted = migrations::migrate(header.version, decrypted)?;
+        let db: DB = msgpack::from_slice(&decrypted)?;
+
+        Ok(State {
+            outer_kdf: header.outer_kdf,
+            inner_kdf: header.inner_kdf,
+            keys: db.keys,
+            secrets: db.secrets,
+            folder_expiry: db.folder_expiry,
+            used: db.used,
+        })
+    }
+
     /// Try to open database file
-    pub fn open(&self, opts: &str) -> Result<File, Error> {
+    pub fn open(&mut self, opts: &str) -> Result<File, Error> {
+        self.acquire_lock()?;
         let path = match self.path {
             Some(ref p) => p,
             None => return Err(Error::IncorrectConf),
@@ -158,11 +471,28 @@ impl DB {
 
         match fs::OpenOptions::new().read(r).write(w).open(db_path) {
             Ok(v) => Ok(v),
-            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(self.create_db()?),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound && !self.read_only => {
+                Ok(self.create_db()?)
+            }
             Err(e) => return Err(Error::IO(e)),
         }
     }
 
+    /// Lock database against other processes, shared
+    /// lock in read-only mode. Lock is held until exit.
+    pub fn acquire_lock(&mut self) -> Result<(), Error> {
+        if self.lock.is_some() {
+            return Ok(());
+        }
+        let path = match self.path {
+            Some(ref p) => p.clone(),
+            None => return Err(Error::IncorrectConf),
+        };
+
+        self.lock = Some(DbLock::acquire(Path::new(&path), self.read_only)?);
+        Ok(())
+    }
+
     /// Create new database file
     pub fn create_db(&self) -> Result<File, Error> {
         // Create dir if not exists yet
@@ -202,15 +532,15 @@ impl DB {
 
         // Validate all keys
         for key in self.keys.iter() {
-            if !key.validate(&passwords) {
+            if !self.is_valid_key(key, &passwords) {
                 return Err(Error::InvalidKey);
             }
         }
 
-        // Decrypt secrets, update keys, encrypt secrets
-        let secret_values = self.decrypt_all_secrets(&passwords)?;
+        // Update keys and rewrap data keys of secrets
+        let unwrap_key = self.get_unwrap_key(&passwords)?;
         self.keys.push(key);
-        self.encrypt_all_secrets(secret_values, &passwords)?;
+        self.rewrap_all_secrets(unwrap_key, &passwords)?;
 
         self.save()?;
 
@@ -221,9 +551,35 @@ impl DB {
     }
 
     /// Add new secret to this database
-    pub fn add_secret(&mut self, args: AddSecretArgs, passwords: Passwords) -> Result<(), Error> {
+    pub fn add_secret(
+        &mut self,
+        mut args: AddSecretArgs,
+        passwords: Passwords,
+    ) -> Result<(), Error> {
         self.should_be_ready()?;
 
+        // Value is either provided or generated
+        match (args.value.is_some(), args.generate.take()) {
+            (true, None) => (),
+            (false, Some(password_args)) => {
+                if args.secret_type != SecretType::Text {
+                    return Err(Error::IncorrectReq);
+                }
+                args.value = Some(self.generate_password(&password_args)?.value);
+            }
+            _ => return Err(Error::IncorrectReq),
+        }
+
+        // Check breaches dataset
+        if args.secret_type == SecretType::Text && !args.allow_breached {
+            if let Some(mut breaches) = self.open_breaches()? {
+                let value = args.value.as_ref().map_or(&[][..], |v| v.as_bytes());
+                if let Some(count) = breaches.count(value)? {
+                    return Err(Error::Breached(count));
+                }
+            }
+        }
+
         // Load DB
         self.load()?;
 
@@ -238,14 +594,14 @@ impl DB {
             _ => {
                 // Validate all keys
                 for key in self.keys.iter() {
-                    if !key.validate(&passwords) {
+                    if !self.is_valid_key(key, &passwords) {
                         return Err(Error::InvalidKey);
                     }
                 }
 
-                // Get ciphers, encrypt secret and add to db
-                let ciphers = DB::get_ciphers(&self.keys, &key_groups, &passwords)?;
-                Secret::from_args(args, Some(ciphers))?
+                // Get group keys, encrypt secret and add to db
+                let group_keys = self.get_group_keys(&key_groups, &passwords)?;
+                Secret::from_args(args, Some(&group_keys))?
             }
         };
 
@@ -258,6 +614,121 @@ impl DB {
         Ok(())
     }
 
+    /// Generate new password
+    pub fn generate_password(&self, args: &PasswordArgs) -> Result<Password, Error> {
+        password::generate(args, self.wordlist_path.as_ref().map(Path::new))
+    }
+
+    /// Open configured breaches dataset
+    fn open_breaches(&self) -> Result<Option<BreachFile>, Error> {
+        match self.breach_path {
+            Some(ref path) => Ok(Some(BreachFile::open(Path::new(path))?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Unlock database: validate keys and derive keys of
+    /// all groups once. Returned token is used instead of
+    /// passwords until lock or timeout.
+    pub fn unlock(&mut self, passwords: Passwords) -> Result<String, Error> {
+        self.should_be_ready()?;
+
+        // Load DB
+        self.load()?;
+
+        // Validate all keys
+        for key in self.keys.iter() {
+            if !self.is_valid_key(key, &passwords) {
+                return Err(Error::InvalidKey);
+            }
+        }
+
+        // Derive group keys
+        let mut keys = vec![];
+        for (_, keys_ids) in self.get_key_groups() {
+            let key = self.get_group_key(&keys_ids, &passwords)?;
+            keys.push(CachedKey {
+                keys_ids: keys_ids,
+                kdf: self.inner_kdf,
+                key: key,
+            });
+        }
+
+        let keys_ids = self.keys.iter().map(|k| k.id.clone()).collect();
+
+        // Unload DB
+        self.unload();
+
+        Ok(self.sessions.open(keys_ids, keys, Instant::now()))
+    }
+
+    /// Lock session of token
+    pub fn lock(&mut self, token: &str) -> Result<(), Error> {
+        match self.sessions.close(token) {
+            true => {
+                self.cache.clear();
+                Ok(())
+            }
+            false => Err(Error::NotFound),
+        }
+    }
+
+    /// Lock all sessions and drop their keys
+    /// along with decrypted database
+    pub fn lock_all(&mut self) {
+        self.sessions.clear();
+        self.session_keys.clear();
+        self.session_keys_ids.clear();
+        self.stamp = None;
+        self.unload();
+        self.cache.clear();
+    }
+
+    /// Lock all sessions if none of them was used
+    /// for idle period. Return true if locked.
+    pub fn lock_if_idle(&mut self, idle: Duration) -> bool {
+        let now = Instant::now();
+
+        // Content cached by expired sessions
+        if !self.sessions.is_active(now) {
+            self.cache.clear();
+        }
+
+        match self.sessions.idle_for(now) {
+            Some(t) if t >= idle && self.sessions.len() > 0 => {
+                self.lock_all();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Run request with keys of session (if token is
+    /// provided) along with passwords of request
+    pub fn with_session<F, R>(
+        &mut self,
+        token: Option<String>,
+        passwords: Passwords,
+        f: F,
+    ) -> Result<R, Error>
+    where
+        F: FnOnce(&mut DB, Passwords) -> Result<R, Error>,
+    {
+        if let Some(token) = token {
+            let session = match self.sessions.get(&token, Instant::now()) {
+                Some(s) => s,
+                None => return Err(Error::SessionExpired),
+            };
+            self.session_keys = session.keys.clone();
+            self.session_keys_ids = session.keys_ids.clone();
+        }
+
+        let res = f(self, passwords);
+        self.session_keys.clear();
+        self.session_keys_ids.clear();
+        res
+    }
+
     /// Remove key
     pub fn rm_key(&mut self, key_name: &str, passwords: Passwords) -> Result<(), Error> {
         self.should_be_ready()?;
@@ -274,16 +745,56 @@ impl DB {
 
         // Validate all keys
         for key in self.keys.iter() {
-            if !key.validate(&passwords) {
+            if !self.is_valid_key(key, &passwords) {
                 return Err(Error::InvalidKey);
             }
         }
 
-        // Decrypt secrets, update keys, encrypt secrets
-        let secret_values = self.decrypt_all_secrets(&passwords)?;
+        // Update keys and rewrap data keys of secrets
+        let unwrap_key = self.get_unwrap_key(&passwords)?;
         self.keys.remove(key_index);
-        self.encrypt_all_secrets(secret_values, &passwords)?;
+        self.rewrap_all_secrets(unwrap_key, &passwords)?;
+
+        self.save()?;
+
+        // Unload DB
+        self.unload();
+
+        Ok(())
+    }
+
+    /// Update secret found by query
+    pub fn update_secret(&mut self, args: UpdateSecretArgs, passwords: Passwords) -> Result<(), Error> {
+        self.should_be_ready()?;
+
+        // Load DB
+        self.load()?;
 
+        // Validate all keys
+        for key in self.keys.iter() {
+            if !self.is_valid_key(key, &passwords) {
+                return Err(Error::InvalidKey);
+            }
+        }
+
+        let secret_index = {
+            let secret = self.find_secret(args.query.clone())?;
+            match self.secrets.iter().position(|s| s.id == secret.id) {
+                Some(i) => i,
+                None => return Err(Error::NotFound),
+            }
+        };
+
+        // Get group keys only if value or fields should be encrypted
+        let key_groups = self.get_key_groups();
+        let encrypt = args.value.is_some() || has_encrypted(&args.set_fields);
+        let group_keys = match (encrypt, key_groups.len()) {
+            (true, n) if n > 0 => Some(self.get_group_keys(&key_groups, &passwords)?),
+            _ => None,
+        };
+
+        // Update secret and save
+        self.secrets[secret_index].update(args, group_keys.as_ref())?;
         self.save()?;
 
         // Unload DB
@@ -301,7 +812,7 @@ impl DB {
 
         // Validate all keys
         for key in self.keys.iter() {
-            if !key.validate(&passwords) {
+            if !self.is_valid_key(key, &passwords) {
                 return Err(Error::InvalidKey);
             }
         }
@@ -335,197 +846,661 @@ impl DB {
         Ok(keys)
     }
 
-    /// List all secrets
-    pub fn list_secrets(&mut self) -> Result<Vec<SecretInfo>, Error> {
+    /// List secrets passing filter
+    pub fn list_secrets(&mut self, filter: &SecretsFilter) -> Result<Vec<SecretInfo>, Error> {
         self.should_be_ready()?;
 
+        let query = match filter.query {
+            Some(ref q) => Query::parse(q)?,
+            None => Query::default(),
+        };
+
         self.load()?;
-        let secrets = self.secrets.iter().map(|s| s.into()).collect();
+        let secrets = self
+            .secrets
+            .iter()
+            .filter(|s| filter.matches(s) && query.matches(s))
+            .map(|s| s.into())
+            .collect();
         self.unload();
 
         Ok(secrets)
     }
 
-    /// Find, decrypt and return secret value with type
-    pub fn get_secret(
+    /// Fuzzy search secrets by name, url and login
+    pub fn search_secrets(
         &mut self,
-        query: Vec<String>,
-        passwords: Passwords,
-    ) -> Result<(Vec<u8>, SecretType), Error> {
+        query: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<SearchResult>, Error> {
         self.should_be_ready()?;
 
-        // Load DB
         self.load()?;
+        let mut results = search::search(&self.secrets, query, &self.used, Local::now().timestamp());
+        self.unload();
 
-        let value_with_type = {
-            // Try to find secret
-            let secret = self.find_secret(query)?;
-            let secret_type = secret.secret_type;
+        if let Some(limit) = limit {
+            results.truncate(limit);
+        }
 
-            if self.keys.len() == 0 {
-                let value = secret.get_plain()?;
-                return Ok((value, secret_type));
-            }
+        Ok(results)
+    }
 
-            // Find available key group for decrypting
-            let key_groups = self.get_key_groups();
-            let valid_group = key_groups
-                .iter()
-                .find(|(_, keys_ids)| {
-                    // Retrive key by id and validate it
-                    match self.keys.iter().find(|k| keys_ids.contains(&k.id)) {
-                        Some(key) => key.validate(&passwords),
-                        None => false,
-                    }
+    /// Find secrets for origin url, the best matches first
+    pub fn match_url(&mut self, url: &str) -> Result<Vec<UrlCandidate>, Error> {
+        self.should_be_ready()?;
+        let origin = url_match::parse(url)?;
+        let suffixes = self.read_suffix_list()?;
+
+        self.load()?;
+        let mut candidates: Vec<UrlCandidate> = self
+            .secrets
+            .iter()
+            .filter_map(|s| {
+                let strength = match s.url {
+                    Some(ref u) => url_match::match_url(u, s.url_match, &origin, &suffixes)?,
+                    None => return None,
+                };
+                Some(UrlCandidate {
+                    secret: s.into(),
+                    strength: strength,
                 })
-                .ok_or(Error::InvalidKey)?;
+            })
+            .collect();
+        self.unload();
 
-            // Retrieve secret value with its type
-            let group_name = valid_group.0.clone();
-            let group_keys = valid_group.1.clone();
-            let cipher = DB::get_group_cipher(&self.keys.clone(), &group_keys, &passwords)?;
-            let value = secret.decrypt(group_name, cipher)?.clone();
-            (value, secret_type)
-        };
+        candidates.sort_by(|a, b| {
+            b.strength
+                .cmp(&a.strength)
+                .then(a.secret.name.cmp(&b.secret.name))
+        });
 
-        // Unload DB
-        self.unload();
+        Ok(candidates)
+    }
 
-        Ok(value_with_type)
+    /// Read configured Public Suffix List or the system one
+    fn read_suffix_list(&self) -> Result<SuffixList, Error> {
+        match self.public_suffix_path {
+            Some(ref path) => SuffixList::read(Path::new(path)),
+            None => match SuffixList::read(Path::new(url_match::DEFAULT_SUFFIX_LIST_PATH)) {
+                Ok(list) => Ok(list),
+                // Secrets aren't matched by domain without list
+                Err(_) => Ok(SuffixList::default()),
+            },
+        }
     }
 
-    /// Try to find secret by name/url/login
-    fn find_secret(&self, args: Vec<String>) -> Result<&Secret, Error> {
-        let maybe_secret = self.secrets.iter().find(|secret| {
-            // Check if all arguments matched
-            args.iter().all(|arg| {
-                let norm_arg = arg.to_lowercase();
-
-                // ID
-                if secret.id.to_lowercase().find(&norm_arg).is_some() {
-                    return true;
-                }
+    /// Decrypt all secrets and find weak, reused and old ones.
+    /// Values are not returned.
+    pub fn audit(&mut self, passwords: Passwords) -> Result<Vec<AuditEntry>, Error> {
+        self.should_be_ready()?;
 
-                // Name
-                if secret.name.to_lowercase().find(&norm_arg).is_some() {
-                    return true;
-                }
+        // Load DB
+        self.load()?;
 
-                // Url
-                if let Some(ref url) = secret.url {
-                    if url.to_lowercase().find(&norm_arg).is_some() {
-                        return true
-                    }
-                }
+        let group_key = match self.keys.len() {
+            0 => None,
+            _ => Some(self.get_valid_group_key(&passwords)?),
+        };
 
-                // Login
-                if let Some(ref login) = secret.login {
-                    if login.to_lowercase().find(&norm_arg).is_some() {
-                        return true;
+        let mut breaches = self.open_breaches()?;
+        let mut audit = Audit::new(self.audit, Local::now().timestamp());
+        for secret in self.secrets.iter() {
+            let value = match group_key {
+                Some((ref group_name, ref group_key)) => secret.decrypt(group_name, group_key)?,
+                None => secret.get_plain()?,
+            };
+            audit.add(secret, &value);
+
+            // Check breaches dataset
+            match breaches {
+                Some(ref mut breaches) if secret.secret_type == SecretType::Text => {
+                    if let Some(count) = breaches.count(&value)? {
+                        audit.add_finding(&secret.id, Finding::Breached { count: count });
                     }
                 }
-
-                return false;
-            })
-        });
-
-        match maybe_secret {
-            Some(s) => Ok(s),
-            None => Err(Error::NotFound),
+                _ => (),
+            }
         }
-    }
-
-    /// Get outer iv value
-    fn get_outer_iv(&self) -> Result<GenericArray<u8, U16>, Error> {
-        let key = match self.key {
-            Some(ref k) => k.as_bytes(),
-            None => return Err(Error::IncorrectConf),
-        };
 
-        // Generate iv
-        let mut hasher = Sha256::new();
-        hasher.input(key);
-        let hashed_key = hasher.result();
-        let (hashed_iv, _) = hashed_key.as_slice().split_at(16);
+        // Unload DB
+        self.unload();
 
-        Ok(*GenericArray::from_slice(hashed_iv))
+        Ok(audit.finish())
     }
 
-    /// Get outer db key (outer)
-    fn get_outer_key(&self) -> Result<[u8; 32], Error> {
-        let key = match self.key {
-            Some(ref k) => k.as_bytes(),
-            None => return Err(Error::IncorrectConf),
+    /// Set rotation period of secrets in folder,
+    /// None or 0 removes it
+    pub fn set_folder_expiry(
+        &mut self,
+        folder: &str,
+        days: Option<u32>,
+        passwords: Passwords,
+    ) -> Result<(), Error> {
+        self.should_be_ready()?;
+        let folder = match normalize_folder(folder) {
+            Some(f) => f,
+            None => return Err(Error::IncorrectReq),
         };
 
-        // Generate salt
-        let mut hasher_1 = Sha256::new();
-        let mut hasher_2 = Sha256::new();
-        hasher_1.input(key);
-        let hashed_key_1 = hasher_1.result();
-        hasher_2.input(hashed_key_1);
-        let hashed_key = hasher_2.result();
+        // Load DB
+        self.load()?;
 
-        // Derive key
-        let mut derived_key = [0u8; 32];
-        let scrypt_params = ScryptParams::new(OUTER_SCRYPT_LOG2_N, OUTER_SCRYPT_R, 1)?;
-        scrypt(&key, &hashed_key, &scrypt_params, &mut derived_key)?;
+        // Validate all keys
+        for key in self.keys.iter() {
+            if !self.is_valid_key(key, &passwords) {
+                return Err(Error::InvalidKey);
+            }
+        }
 
-        Ok(derived_key)
-    }
+        match days {
+            Some(d) if d > 0 => {
+                self.folder_expiry.insert(folder, d);
+            }
+            _ => {
+                self.folder_expiry.remove(&folder);
+            }
+        }
+        self.save()?;
+
+        // Unload DB
+        self.unload();
+
+        Ok(())
+    }
+
+    /// List secrets that are overdue or expire in provided days
+    pub fn list_expiring(&mut self, within_days: u32) -> Result<Vec<ExpiringSecret>, Error> {
+        self.should_be_ready()?;
+
+        self.load()?;
+        let expiring = expiry::expiring(
+            &self.secrets,
+            &self.folder_expiry,
+            Local::now().timestamp(),
+            within_days as i64 * 86400,
+        );
+        self.unload();
+
+        Ok(expiring)
+    }
+
+    /// Queue notifications about newly overdue secrets
+    /// and return their number
+    pub fn check_expiry(&mut self) -> Result<usize, Error> {
+        let expiring = self.list_expiring(0)?;
+        Ok(self.notifier.expired(expiring))
+    }
+
+    /// Subscribe client listening on unix socket to events.
+    /// Socket should be owned by user of daemon.
+    pub fn subscribe(&mut self, socket_path: &str) -> Result<(), Error> {
+        let path = Path::new(socket_path);
+        if !path.is_absolute() {
+            return Err(Error::IncorrectReq);
+        }
+        notify::check_socket(path)?;
+        self.notifier.subscribe(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Unsubscribe client from events
+    pub fn unsubscribe(&mut self, socket_path: &str) -> Result<(), Error> {
+        match self.notifier.unsubscribe(Path::new(socket_path)) {
+            true => Ok(()),
+            false => Err(Error::NotFound),
+        }
+    }
+
+    /// Rename tag in all secrets
+    pub fn rename_tag(&mut self, from: &str, to: &str, passwords: Passwords) -> Result<(), Error> {
+        self.should_be_ready()?;
+
+        // Load DB
+        self.load()?;
+
+        // Validate all keys
+        for key in self.keys.iter() {
+            if !self.is_valid_key(key, &passwords) {
+                return Err(Error::InvalidKey);
+            }
+        }
+
+        let mut renamed = 0;
+        for secret in self.secrets.iter_mut() {
+            if secret.rename_tag(from, to) {
+                renamed += 1;
+            }
+        }
+        if renamed == 0 {
+            return Err(Error::NotFound);
+        }
+
+        self.save()?;
+
+        // Unload DB
+        self.unload();
+
+        Ok(())
+    }
+
+    /// Move folder with all subfolders and secrets
+    pub fn move_folder(&mut self, from: &str, to: &str, passwords: Passwords) -> Result<(), Error> {
+        self.should_be_ready()?;
+        let from = normalize_folder(from).ok_or(Error::IncorrectReq)?;
+        let to = normalize_folder(to).unwrap_or_default();
+
+        // Load DB
+        self.load()?;
+
+        // Validate all keys
+        for key in self.keys.iter() {
+            if !self.is_valid_key(key, &passwords) {
+                return Err(Error::InvalidKey);
+            }
+        }
+
+        let mut moved = 0;
+        for secret in self.secrets.iter_mut() {
+            if secret.move_folder(&from, &to) {
+                moved += 1;
+            }
+        }
+        if moved == 0 {
+            return Err(Error::NotFound);
+        }
+
+        self.save()?;
+
+        // Unload DB
+        self.unload();
+
+        Ok(())
+    }
+
+    /// List backups of database, newest first
+    pub fn list_backups(&self) -> Result<Vec<BackupInfo>, Error> {
+        let backups_path = match self.backups_path {
+            Some(ref b) => b,
+            None => return Err(Error::IncorrectConf),
+        };
+
+        backup::list(Path::new(backups_path))
+    }
+
+    /// Replace database with backup.
+    /// Backup should be decryptable with current outer key.
+    pub fn restore_backup(&mut self, name: &str) -> Result<(), Error> {
+        self.should_be_ready()?;
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        self.acquire_lock()?;
+        let path = self.path.clone().ok_or(Error::IncorrectConf)?;
+        let backups_path = match self.backups_path {
+            Some(ref b) => b.clone(),
+            None => return Err(Error::IncorrectConf),
+        };
+        let backup_dir = Path::new(&backups_path);
+
+        // Check that backup is readable
+        let content = backup::read(backup_dir, name)?;
+        let (header, decrypted) = self.decrypt_content(&content)?;
+        let decrypted = migrations::migrate(header.version, decrypted)?;
+        let _: DB = msgpack::from_slice(&decrypted)?;
+
+        // Keep current database
+        if let Ok(current) = fs::read(&path) {
+            if !current.is_empty() {
+                backup::create(backup_dir, &current)?;
+            }
+        }
+
+        // Swap it in
+        self.cache.clear();
+        utils::write_atomic(Path::new(&path), |db_file| {
+            db_file.write_all(&content)?;
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    /// Re-derive outer and inner keys with configured kdf
+    /// params and rewrite database
+    pub fn rekey_kdf(&mut self, passwords: Passwords) -> Result<(), Error> {
+        self.should_be_ready()?;
+
+        // Load DB
+        self.load()?;
+
+        // Validate all keys
+        for key in self.keys.iter() {
+            if !self.is_valid_key(key, &passwords) {
+                return Err(Error::InvalidKey);
+            }
+        }
+
+        // Unwrap data keys with current params, wrap with new ones
+        let unwrap_key = self.get_unwrap_key(&passwords)?;
+        self.inner_kdf = self.kdf;
+        self.rewrap_all_secrets(unwrap_key, &passwords)?;
+        self.outer_kdf = self.kdf;
+
+        self.save()?;
+
+        // Unload DB
+        self.unload();
+
+        Ok(())
+    }
+
+    /// Find, decrypt and return secret value with type
+    pub fn get_secret(
+        &mut self,
+        query: Vec<String>,
+        passwords: Passwords,
+    ) -> Result<(SecretBuf, SecretType), Error> {
+        let (value, secret_type, _) = self.get_secret_with_fields(query, None, passwords)?;
+        Ok((value, secret_type))
+    }
+
+    /// Find, decrypt and return secret value with type and
+    /// custom fields. If field name is provided, only value
+    /// of this field is returned.
+    pub fn get_secret_with_fields(
+        &mut self,
+        query: Vec<String>,
+        field: Option<String>,
+        passwords: Passwords,
+    ) -> Result<(SecretBuf, SecretType, Vec<FieldValue>), Error> {
+        self.should_be_ready()?;
+
+        // Load DB
+        self.load()?;
+
+        let (result, secret_id) = {
+            // Try to find secret
+            let secret = self.find_secret(query)?;
+            let secret_type = secret.secret_type;
+
+            // Find available key group for decrypting
+            let group_key = match self.keys.len() {
+                0 => None,
+                _ => Some(self.get_valid_group_key(&passwords)?),
+            };
+            let unwrap_key = group_key.as_ref().map(|(g, k)| (g.as_str(), k));
+
+            // Retrieve value of secret or of its field
+            let mut fields = secret.fields(unwrap_key)?;
+            let result = match field {
+                Some(name) => match fields.iter().position(|f| f.name == name) {
+                    Some(i) => (fields.remove(i).value, secret_type, vec![]),
+                    None => return Err(Error::NotFound),
+                },
+                None => {
+                    // Seed of TOTP secret is read only by get-otp
+                    if secret_type == SecretType::Totp {
+                        return Err(Error::InvalidOtp);
+                    }
+                    let value = match unwrap_key {
+                        Some((group_name, group_key)) => secret.decrypt(group_name, group_key)?,
+                        None => secret.get_plain()?,
+                    };
+                    (value, secret_type, fields)
+                }
+            };
+            (result, secret.id.clone())
+        };
+
+        // Unload DB
+        self.unload();
+        self.used.insert(secret_id, Local::now().timestamp());
+
+        Ok(result)
+    }
+
+    /// Find TOTP secret and return its current code.
+    /// Seed itself never leaves the database.
+    pub fn get_otp(&mut self, query: Vec<String>, passwords: Passwords) -> Result<OtpCode, Error> {
+        self.should_be_ready()?;
+
+        // Load DB
+        self.load()?;
+
+        let (code, secret_id) = {
+            let secret = self.find_secret(query)?;
+            let params = match (secret.secret_type, secret.otp) {
+                (SecretType::Totp, Some(params)) => params,
+                _ => return Err(Error::InvalidOtp),
+            };
+
+            let seed = match self.keys.len() {
+                0 => secret.get_plain()?,
+                _ => {
+                    let (group_name, group_key) = self.get_valid_group_key(&passwords)?;
+                    secret.decrypt(&group_name, &group_key)?
+                }
+            };
+            (otp::totp(&seed, &params, Local::now().timestamp())?, secret.id.clone())
+        };
+
+        // Unload DB
+        self.unload();
+        self.used.insert(secret_id, Local::now().timestamp());
+
+        Ok(code)
+    }
+
+    /// Find secret and return its decrypted previous values
+    pub fn get_secret_history(
+        &mut self,
+        query: Vec<String>,
+        passwords: Passwords,
+    ) -> Result<Vec<HistoryValue>, Error> {
+        self.should_be_ready()?;
+
+        // Load DB
+        self.load()?;
+
+        let history = {
+            let secret = self.find_secret(query)?;
+            if secret.secret_type == SecretType::Totp {
+                return Err(Error::InvalidOtp);
+            }
+            match self.keys.len() {
+                0 => secret.history(None),
+                _ => {
+                    let (group_name, group_key) = self.get_valid_group_key(&passwords)?;
+                    secret.history(Some((&group_name, &group_key)))
+                }
+            }
+        };
+
+        // Unload DB
+        self.unload();
+
+        history
+    }
+
+    /// Make previous value of secret the current one
+    pub fn restore_secret_history(
+        &mut self,
+        query: Vec<String>,
+        index: usize,
+        passwords: Passwords,
+    ) -> Result<(), Error> {
+        self.should_be_ready()?;
+
+        // Load DB
+        self.load()?;
+
+        // Validate all keys
+        for key in self.keys.iter() {
+            if !self.is_valid_key(key, &passwords) {
+                return Err(Error::InvalidKey);
+            }
+        }
+
+        let secret_index = {
+            let secret = self.find_secret(query)?;
+            match self.secrets.iter().position(|s| s.id == secret.id) {
+                Some(i) => i,
+                None => return Err(Error::NotFound),
+            }
+        };
+
+        // Restore value and save
+        let key_groups = self.get_key_groups();
+        let group_keys = match key_groups.len() {
+            0 => None,
+            _ => Some(self.get_group_keys(&key_groups, &passwords)?),
+        };
+        self.secrets[secret_index].restore_history(index, group_keys.as_ref())?;
+        self.save()?;
+
+        // Unload DB
+        self.unload();
+
+        Ok(())
+    }
+
+    /// Find the only secret matching query terms
+    fn find_secret(&self, args: Vec<String>) -> Result<&Secret, Error> {
+        let query = Query::from_terms(&args)?;
+        let mut found: Vec<&Secret> = self.secrets.iter().filter(|s| query.matches(s)).collect();
+
+        match found.len() {
+            0 => Err(Error::NotFound),
+            1 => Ok(found.remove(0)),
+            _ => Err(Error::Ambiguous(
+                found.iter().map(|s| (s.id.clone(), s.name.clone())).collect(),
+            )),
+        }
+    }
+
+    /// Get legacy outer iv value (format version 0)
+    fn get_legacy_outer_iv(&self) -> Result<GenericArray<u8, U16>, Error> {
+        let key = match self.key {
+            Some(ref k) => k.as_bytes(),
+            None => return Err(Error::IncorrectConf),
+        };
+
+        // Generate iv
+        let mut hasher = Sha256::new();
+        hasher.input(key);
+        let hashed_key = hasher.result();
+        let (hashed_iv, _) = hashed_key.as_slice().split_at(16);
+
+        Ok(*GenericArray::from_slice(hashed_iv))
+    }
+
+    /// Get legacy outer nonce value (format version 1)
+    fn get_legacy_outer_nonce(&self) -> Result<GenericArray<u8, U12>, Error> {
+        let key = match self.key {
+            Some(ref k) => k.as_bytes(),
+            None => return Err(Error::IncorrectConf),
+        };
+
+        // Generate nonce
+        let mut hasher = Sha256::new();
+        hasher.input(key);
+        let hashed_key = hasher.result();
+        let (hashed_nonce, _) = hashed_key.as_slice().split_at(12);
+
+        Ok(*GenericArray::from_slice(hashed_nonce))
+    }
+
+    /// Get legacy outer salt value (format versions 0 and 1)
+    fn get_legacy_outer_salt(&self) -> Result<Vec<u8>, Error> {
+        let key = match self.key {
+            Some(ref k) => k.as_bytes(),
+            None => return Err(Error::IncorrectConf),
+        };
+
+        let mut hasher_1 = Sha256::new();
+        let mut hasher_2 = Sha256::new();
+        hasher_1.input(key);
+        let hashed_key_1 = hasher_1.result();
+        hasher_2.input(hashed_key_1);
+
+        Ok(Vec::from(hasher_2.result().as_slice()))
+    }
+
+    /// Get outer db key (outer)
+    fn get_outer_key(&self, salt: &[u8], kdf: &KdfParams) -> Result<SecretBuf, Error> {
+        let key = match self.key {
+            Some(ref k) => k.as_bytes(),
+            None => return Err(Error::IncorrectConf),
+        };
+
+        // Derive key
+        let mut derived_key = SecretBuf::new(vec![0u8; 32]);
+        scrypt(&key, salt, &kdf.scrypt()?, &mut derived_key)?;
+
+        Ok(derived_key)
+    }
 
     /// Get hashed key and iv from provided secret
-    fn get_inner_key_iv(group_key: Vec<u8>) -> Result<([u8; 32], GenericArray<u8, U16>), Error> {
+    fn get_inner_key_iv(group_secret: SecretBuf, kdf: &KdfParams) -> Result<GroupKey, Error> {
         // Get hashed parts
         let mut hasher = Sha256::new();
-        hasher.input(group_key.clone());
+        hasher.input(&group_secret[..]);
         let garr = hasher.result();
         let (h_iv, h_salt) = garr.as_slice().split_at(16);
 
         // Key
-        let mut key = [0u8; 32];
-        let scrypt_params = ScryptParams::new(INNER_SCRYPT_LOG2_N, INNER_SCRYPT_R, 1)?;
-        scrypt(&group_key, &h_salt[..4], &scrypt_params, &mut key)?;
+        let mut key = SecretBuf::new(vec![0u8; 32]);
+        scrypt(&group_secret, &h_salt[..4], &kdf.scrypt()?, &mut key)?;
 
         // IV
         let iv = *GenericArray::from_slice(h_iv);
 
-        Ok((key, iv))
+        Ok(GroupKey::new(key, iv))
     }
 
-    /// Get ciphers by keys groups
-    fn get_ciphers(
-        keys: &Vec<Key>,
+    /// Get group keys by keys groups
+    fn get_group_keys(
+        &self,
         key_groups: &HashMap<String, Vec<String>>,
         passwords: &Passwords,
-    ) -> Result<HashMap<String, Aes256Cbc>, Error> {
-        let mut ciphers = HashMap::with_capacity(3);
+    ) -> Result<HashMap<String, GroupKey>, Error> {
+        let mut group_keys = HashMap::with_capacity(3);
 
         for (g, keys_ids) in key_groups.iter() {
-            let c = DB::get_group_cipher(keys, keys_ids, passwords)?;
-            ciphers.insert(g.clone(), c);
+            let k = self.get_group_key(keys_ids, passwords)?;
+            group_keys.insert(g.clone(), k);
         }
 
-        Ok(ciphers)
+        Ok(group_keys)
     }
 
-    /// Get cipher from group of keys
-    fn get_group_cipher(
-        keys: &Vec<Key>,
+    /// Get derived key of group of keys
+    fn get_group_key(
+        &self,
         keys_ids: &Vec<String>,
         passwords: &Passwords,
-    ) -> Result<Aes256Cbc, Error> {
-        let mut group_secret: Vec<u8> = Vec::with_capacity(1024);
+    ) -> Result<GroupKey, Error> {
+        // Key derived at unlock of current session
+        let cached = self
+            .session_keys
+            .iter()
+            .find(|k| k.keys_ids == *keys_ids && k.kdf == self.inner_kdf);
+        if let Some(cached) = cached {
+            return Ok(cached.key.clone());
+        }
+
+        let mut group_secret = SecretBuf::with_capacity(1024);
 
         for key_id in keys_ids.iter() {
-            let key = match keys.iter().find(|k| k.id == *key_id) {
+            let key = match self.keys.iter().find(|k| k.id == *key_id) {
                 Some(k) => k,
                 None => return Err(Error::InvalidKey),
             };
 
             match key.key_type {
                 KeyType::Text => match passwords.iter().find(|(kn, _)| **kn == key.name) {
-                    Some((_, kv)) => group_secret.append(&mut kv.clone().into_bytes()),
+                    Some((_, kv)) => group_secret.extend_from_slice(kv.as_bytes()),
                     None => return Err(Error::InvalidKey),
                 },
                 KeyType::File => {
@@ -535,63 +1510,76 @@ impl DB {
                     let mut hasher = Sha256::new();
                     hasher.input(file_data);
                     let hr = hasher.result();
-                    let mut h = hr.as_slice();
-                    group_secret.append(&mut Vec::from(h).clone());
+                    group_secret.extend_from_slice(hr.as_slice());
                 }
             }
         }
 
-        let (key, iv) = DB::get_inner_key_iv(group_secret)?;
-        Ok(Aes256Cbc::new_var(&key, &iv)?)
+        DB::get_inner_key_iv(group_secret, &self.inner_kdf)
     }
 
-    /// Decrypt all secrets and return values
-    fn decrypt_all_secrets(&mut self, passwords: &Passwords) -> Result<Vec<Vec<u8>>, Error> {
-        // Get first keys group
+    /// Check if key was validated by session or its
+    /// password is provided
+    fn is_valid_key(&self, key: &Key, passwords: &Passwords) -> bool {
+        self.session_keys_ids.contains(&key.id) || key.validate(passwords)
+    }
+
+    /// Find available key group for decrypting and get its key
+    fn get_valid_group_key(&self, passwords: &Passwords) -> Result<(String, GroupKey), Error> {
         let key_groups = self.get_key_groups();
-        let mut values = Vec::with_capacity(self.secrets.len());
+        let valid_group = key_groups
+            .iter()
+            .find(|(_, keys_ids)| {
+                // Retrive key by id and validate it
+                match self.keys.iter().find(|k| keys_ids.contains(&k.id)) {
+                    Some(key) => self.is_valid_key(key, passwords),
+                    None => false,
+                }
+            })
+            .ok_or(Error::InvalidKey)?;
 
-        if key_groups.len() > 0 {
-            let group = match key_groups.into_iter().next() {
-                Some(g) => g,
-                None => return Err(Error::NotEnoughKeys),
-            };
+        let group_key = self.get_group_key(valid_group.1, passwords)?;
+        Ok((valid_group.0.clone(), group_key))
+    }
 
-            for secret in self.secrets.iter_mut() {
-                let group_keys = group.1.clone();
-                let cipher = DB::get_group_cipher(&self.keys.clone(), &group_keys, passwords)?;
-                values.push(secret.decrypt(group.0.clone(), cipher)?);
-            }
-        } else {
-            for secret in self.secrets.iter_mut() {
-                values.push(secret.get_plain()?)
-            }
+    /// Get key of any keys group for unwrapping data
+    /// keys of secrets. Keys should be validated.
+    fn get_unwrap_key(&self, passwords: &Passwords) -> Result<Option<(String, GroupKey)>, Error> {
+        if self.secrets.is_empty() {
+            return Ok(None);
         }
 
-        Ok(values)
+        match self.get_key_groups().into_iter().next() {
+            Some((group_name, keys_ids)) => {
+                let group_key = self.get_group_key(&keys_ids, passwords)?;
+                Ok(Some((group_name, group_key)))
+            }
+            None => Ok(None),
+        }
     }
 
-    /// Update values of all secrets with provided
-    /// values vector
-    fn encrypt_all_secrets(
+    /// Wrap data keys of all secrets for current keys groups
+    fn rewrap_all_secrets(
         &mut self,
-        values: Vec<Vec<u8>>,
+        unwrap_key: Option<(String, GroupKey)>,
         passwords: &Passwords,
     ) -> Result<(), Error> {
+        if self.secrets.is_empty() {
+            return Ok(());
+        }
+
         let key_groups = self.get_key_groups();
+        let group_keys = self.get_group_keys(&key_groups, passwords)?;
+        let unwrap_key = unwrap_key.as_ref().map(|(g, k)| (g.as_str(), k));
 
-        if key_groups.len() > 0 {
-            for (s, v) in self.secrets.iter_mut().zip(values) {
-                let ciphers = DB::get_ciphers(&self.keys.clone(), &key_groups, &passwords)?;
-                s.encrypt(&v, ciphers)?;
-            }
-        } else {
-            self.secrets
-                .iter_mut()
-                .zip(values)
-                .for_each(|(s, v)| s.set_plain(v.clone()));
+        let mut secrets = Vec::with_capacity(self.secrets.len());
+        for secret in self.secrets.iter() {
+            secrets.push(secret.rewrapped(unwrap_key, &group_keys)?);
         }
 
+        // Replace secrets only when all of them are rewrapped
+        self.secrets = secrets;
+
         Ok(())
     }
 
@@ -614,10 +1602,71 @@ impl DB {
     }
 }
 
+/// Hash of raw database content
+fn content_hash(content: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.input(content);
+    hasher.result().to_vec()
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::process;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use audit;
+    use cache::Content;
+    use chrono::Local;
     use config;
     use db;
+    use errors::Error;
+
+    use header::{Header, KdfParams, DB_VERSION};
+    use key::{AddKeyArgs, Key, KeyType, Passwords};
+    use password::PasswordArgs;
+    use secret::{
+        AddSecretArgs, FieldArgs, FieldKind, Secret, SecretType, SecretsFilter, UpdateSecretArgs,
+    };
+    use secure::SecretString;
+    use url_match::UrlMatch;
+    use utils;
+
+    fn test_conf() -> config::Config {
+        let path = env::temp_dir().join(format!("pasd_test_{}", utils::uid()));
+        config::Config {
+            db_path: Some(path.to_string_lossy().into_owned()),
+            db_key: Some("outer".to_string()),
+            scrypt_log2_n: Some(1),
+            scrypt_r: Some(1),
+            scrypt_p: Some(1),
+            ..Default::default()
+        }
+    }
+
+    fn test_secret_args(name: &str) -> AddSecretArgs {
+        AddSecretArgs {
+            secret_type: SecretType::Text,
+            name: name.to_string(),
+            value: Some(SecretString::new("value".to_string())),
+            url: None,
+            login: None,
+            url_match: UrlMatch::default(),
+            otp: None,
+            generate: None,
+            allow_breached: false,
+            expiry_days: None,
+            fields: vec![],
+            tags: vec![],
+            folder: None,
+            passwords: HashMap::new(),
+            token: None,
+        }
+    }
 
     #[test]
     fn new() {
@@ -626,10 +1675,967 @@ mod tests {
             db_key: Some("bbb".to_string()),
             ipc_socket_path: Some("ccc".to_string()),
             backups_path: Some("ddd".to_string()),
+            backups_keep_last: Some(3),
+            ..Default::default()
         };
         let new_db = db::DB::new(&conf);
 
         assert_eq!(new_db.path, Some("aaa".to_string()));
         assert_eq!(new_db.backups_path, Some("ddd".to_string()));
+        assert_eq!(new_db.kdf, KdfParams::default());
+        assert_eq!(new_db.retention.keep_last, 3);
+        assert_eq!(new_db.retention.keep_daily, 7);
+    }
+
+    fn test_db_with_secret(conf: &config::Config) -> (db::DB, Passwords) {
+        let mut passwords = HashMap::new();
+        passwords.insert("Key".to_string(), SecretString::new("pass".to_string()));
+
+        let mut test_db = db::DB::new(conf);
+        let key = Key::from_args(AddKeyArgs {
+            key_type: KeyType::Text,
+            name: "Key".to_string(),
+            group: None,
+            value: SecretString::new("pass".to_string()),
+            passwords: HashMap::new(),
+            token: None,
+        }).unwrap();
+        test_db.add_key(key, HashMap::new()).unwrap();
+        test_db
+            .add_secret(test_secret_args("Secret"), passwords.clone())
+            .unwrap();
+
+        (test_db, passwords)
+    }
+
+    /// Copy database written in the format of provided version.
+    /// Fixtures use db key "outer" and keep secret "Secret" with
+    /// value "value" encrypted for key "Key" with password "pass".
+    fn copy_fixture(path: &str, version: u8) {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(format!("db.v{}", version));
+        fs::copy(fixture, path).unwrap();
+    }
+
+    #[test]
+    fn upgrade_old_formats() {
+        for version in 0..DB_VERSION {
+            let conf = test_conf();
+            let path = conf.db_path.clone().unwrap();
+            copy_fixture(&path, version);
+            let mut test_db = db::DB::new(&conf);
+            let mut passwords = HashMap::new();
+            passwords.insert("Key".to_string(), SecretString::new("pass".to_string()));
+
+            let (value, _) = test_db
+                .get_secret(vec!["Secret".to_string()], passwords)
+                .unwrap();
+            assert_eq!(value.to_vec(), Vec::from("value"));
+
+            // Database is upgraded and old one is kept
+            let data = fs::read(&path).unwrap();
+            assert_eq!(Header::read(&mut &data[..]).unwrap().version, DB_VERSION);
+            let backup_path = format!("{}.v{}.bak", path, version);
+            let backup = fs::read(&backup_path).unwrap();
+            assert_eq!(Header::read(&mut &backup[..]).unwrap().version, version);
+
+            fs::remove_file(&path).unwrap();
+            fs::remove_file(&backup_path).unwrap();
+        }
+    }
+
+    #[test]
+    fn add_key_rewraps_data_keys() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let (mut test_db, mut passwords) = test_db_with_secret(&conf);
+        test_db.load().unwrap();
+        let data = test_db.secrets[0].data.clone();
+        test_db.unload();
+
+        // Add key of another group
+        passwords.insert("Key2".to_string(), SecretString::new("pass2".to_string()));
+        let key = Key::from_args(AddKeyArgs {
+            key_type: KeyType::Text,
+            name: "Key2".to_string(),
+            group: None,
+            value: SecretString::new("pass2".to_string()),
+            passwords: HashMap::new(),
+            token: None,
+        }).unwrap();
+        test_db.add_key(key, passwords).unwrap();
+
+        // Secret data is untouched, data key is wrapped for both groups
+        test_db.load().unwrap();
+        assert_eq!(test_db.secrets[0].data, data);
+        assert_eq!(test_db.secrets[0].values.len(), 2);
+        test_db.unload();
+
+        let mut passwords = HashMap::new();
+        passwords.insert("Key2".to_string(), SecretString::new("pass2".to_string()));
+        let (value, _) = test_db
+            .get_secret(vec!["Secret".to_string()], passwords)
+            .unwrap();
+        assert_eq!(value.to_vec(), Vec::from("value"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rekey_kdf() {
+        let mut conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let (_, passwords) = test_db_with_secret(&conf);
+
+        // Rekey with stronger params
+        conf.scrypt_log2_n = Some(2);
+        conf.scrypt_r = Some(2);
+        let mut test_db = db::DB::new(&conf);
+        test_db.rekey_kdf(passwords.clone()).unwrap();
+
+        let data = fs::read(&path).unwrap();
+        let header = Header::read(&mut &data[..]).unwrap();
+        let kdf = KdfParams {
+            log2_n: 2,
+            r: 2,
+            p: 1,
+        };
+        assert_eq!(header.outer_kdf, kdf);
+        assert_eq!(header.inner_kdf, kdf);
+
+        // Data is still there
+        let (value, _) = test_db
+            .get_secret(vec!["Secret".to_string()], passwords)
+            .unwrap();
+        assert_eq!(value.to_vec(), Vec::from("value"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn fresh_header_on_save() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let mut test_db = db::DB::new(&conf);
+        test_db
+            .add_secret(test_secret_args("Secret"), HashMap::new())
+            .unwrap();
+        let data_1 = fs::read(&path).unwrap();
+
+        test_db.load().unwrap();
+        test_db.save().unwrap();
+        let data_2 = fs::read(&path).unwrap();
+
+        // Salt, nonce and ciphertext should differ between saves
+        let header_1 = Header::read(&mut &data_1[..]).unwrap();
+        let header_2 = Header::read(&mut &data_2[..]).unwrap();
+        assert!(header_1.salt != header_2.salt);
+        assert!(header_1.nonce != header_2.nonce);
+        assert!(data_1[data_1.len() - 16..] != data_2[data_2.len() - 16..]);
+
+        // And still be readable
+        test_db.load().unwrap();
+        assert_eq!(test_db.secrets.len(), 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn restore_backup() {
+        let mut conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let backups_dir = env::temp_dir().join(format!("pasd_test_backups_{}", utils::uid()));
+        conf.backups_path = Some(backups_dir.to_string_lossy().into_owned());
+        let mut test_db = db::DB::new(&conf);
+        test_db
+            .add_secret(test_secret_args("Secret"), HashMap::new())
+            .unwrap();
+        let backup_name = test_db.list_backups().unwrap()[0].name.clone();
+        test_db
+            .add_secret(test_secret_args("Secret2"), HashMap::new())
+            .unwrap();
+
+        // Broken backup is not swapped in
+        let broken_name = "pasd_backup_20000101000000000";
+        fs::write(backups_dir.join(broken_name), b"broken").unwrap();
+        assert!(test_db.restore_backup(broken_name).is_err());
+        assert!(test_db.restore_backup("../../etc/passwd").is_err());
+        test_db.load().unwrap();
+        assert_eq!(test_db.secrets.len(), 2);
+        test_db.unload();
+
+        // Valid one is
+        test_db.restore_backup(&backup_name).unwrap();
+        test_db.load().unwrap();
+        assert_eq!(test_db.secrets.len(), 1);
+        test_db.unload();
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_dir_all(&backups_dir).unwrap();
+    }
+
+    #[test]
+    fn update_secret() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let (mut test_db, passwords) = test_db_with_secret(&conf);
+        test_db.load().unwrap();
+        let (id, date) = (test_db.secrets[0].id.clone(), test_db.secrets[0].date);
+        test_db.unload();
+
+        let args = UpdateSecretArgs {
+            query: vec!["Secret".to_string()],
+            name: Some("Renamed".to_string()),
+            value: Some(SecretString::new("new value".to_string())),
+            url: None,
+            login: None,
+            url_match: None,
+            set_fields: vec![],
+            remove_fields: vec![],
+            tags: None,
+            folder: None,
+            expiry_days: None,
+            passwords: passwords.clone(),
+            token: None,
+        };
+        test_db.update_secret(args, passwords.clone()).unwrap();
+
+        // Same secret with new value
+        test_db.load().unwrap();
+        assert_eq!(test_db.secrets.len(), 1);
+        assert_eq!(test_db.secrets[0].id, id);
+        assert_eq!(test_db.secrets[0].date, date);
+        assert!(test_db.secrets[0].modified.is_some());
+        test_db.unload();
+        let (value, _) = test_db
+            .get_secret(vec!["Renamed".to_string()], passwords)
+            .unwrap();
+        assert_eq!(value.to_vec(), Vec::from("new value"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn secret_history() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let (mut test_db, passwords) = test_db_with_secret(&conf);
+        let query = vec!["Secret".to_string()];
+
+        for value in ["second", "third"].iter() {
+            let args = UpdateSecretArgs {
+                query: query.clone(),
+                name: None,
+                value: Some(SecretString::new(value.to_string())),
+                url: None,
+                login: None,
+                url_match: None,
+                set_fields: vec![],
+                remove_fields: vec![],
+                tags: None,
+                folder: None,
+                expiry_days: None,
+                passwords: passwords.clone(),
+                token: None,
+            };
+            test_db.update_secret(args, passwords.clone()).unwrap();
+        }
+
+        // Newest first
+        let history = test_db
+            .get_secret_history(query.clone(), passwords.clone())
+            .unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].value.to_vec(), Vec::from("second"));
+        assert_eq!(history[1].value.to_vec(), Vec::from("value"));
+
+        // Restore the first value
+        test_db
+            .restore_secret_history(query.clone(), 1, passwords.clone())
+            .unwrap();
+        let (value, _) = test_db.get_secret(query.clone(), passwords.clone()).unwrap();
+        assert_eq!(value.to_vec(), Vec::from("value"));
+        let history = test_db.get_secret_history(query, passwords).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].value.to_vec(), Vec::from("third"));
+        assert_eq!(history[1].value.to_vec(), Vec::from("second"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn custom_fields() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let (mut test_db, passwords) = test_db_with_secret(&conf);
+        let query = vec!["Secret".to_string()];
+
+        let args = UpdateSecretArgs {
+            query: query.clone(),
+            name: None,
+            value: None,
+            url: None,
+            login: None,
+            url_match: None,
+            set_fields: vec![
+                FieldArgs {
+                    name: "PIN".to_string(),
+                    kind: FieldKind::Encrypted,
+                    value: SecretString::new("1234".to_string()),
+                },
+                FieldArgs {
+                    name: "Account".to_string(),
+                    kind: FieldKind::Public,
+                    value: SecretString::new("42".to_string()),
+                },
+            ],
+            remove_fields: vec![],
+            tags: None,
+            folder: None,
+            expiry_days: None,
+            passwords: passwords.clone(),
+            token: None,
+        };
+        test_db.update_secret(args, passwords.clone()).unwrap();
+
+        // Only public values are listed
+        let secrets = test_db.list_secrets(&SecretsFilter::default()).unwrap();
+        assert_eq!(secrets[0].fields.len(), 2);
+        assert_eq!(secrets[0].fields[0].value, None);
+        assert_eq!(secrets[0].fields[1].value, Some("42".to_string()));
+
+        // Value with all fields
+        let (value, _, fields) = test_db
+            .get_secret_with_fields(query.clone(), None, passwords.clone())
+            .unwrap();
+        assert_eq!(value.to_vec(), Vec::from("value"));
+        assert_eq!(fields[0].value.to_vec(), Vec::from("1234"));
+
+        // Single field
+        let (value, _, fields) = test_db
+            .get_secret_with_fields(query.clone(), Some("PIN".to_string()), passwords.clone())
+            .unwrap();
+        assert_eq!(value.to_vec(), Vec::from("1234"));
+        assert!(fields.is_empty());
+        assert!(test_db
+            .get_secret_with_fields(query.clone(), Some("None".to_string()), passwords.clone())
+            .is_err());
+
+        // Remove field without setting value or other fields
+        let args = UpdateSecretArgs {
+            query: query.clone(),
+            name: None,
+            value: None,
+            url: None,
+            login: None,
+            url_match: None,
+            set_fields: vec![],
+            remove_fields: vec!["PIN".to_string()],
+            tags: None,
+            folder: None,
+            expiry_days: None,
+            passwords: passwords.clone(),
+            token: None,
+        };
+        test_db.update_secret(args, passwords.clone()).unwrap();
+
+        let secrets = test_db.list_secrets(&SecretsFilter::default()).unwrap();
+        assert_eq!(secrets[0].fields.len(), 1);
+        assert_eq!(secrets[0].fields[0].name, "Account");
+        let (value, _, fields) = test_db
+            .get_secret_with_fields(query, None, passwords)
+            .unwrap();
+        assert_eq!(value.to_vec(), Vec::from("value"));
+        assert_eq!(fields.len(), 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tags_and_folders() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let mut test_db = db::DB::new(&conf);
+        for (name, folder) in [("A", "work/servers"), ("B", "work"), ("C", "home")].iter() {
+            let mut args = test_secret_args(name);
+            args.tags = vec!["ssh".to_string()];
+            args.folder = Some(folder.to_string());
+            test_db.add_secret(args, HashMap::new()).unwrap();
+        }
+
+        let filter = SecretsFilter {
+            tags: vec!["ssh".to_string()],
+            folder: Some("work".to_string()),
+            query: None,
+        };
+        assert_eq!(test_db.list_secrets(&filter).unwrap().len(), 2);
+
+        // Move folder and rename tag
+        test_db.move_folder("work", "old/work", HashMap::new()).unwrap();
+        test_db.rename_tag("ssh", "shell", HashMap::new()).unwrap();
+        assert!(test_db.rename_tag("ssh", "shell", HashMap::new()).is_err());
+
+        let secrets = test_db.list_secrets(&SecretsFilter::default()).unwrap();
+        assert_eq!(secrets[0].folder, Some("old/work/servers".to_string()));
+        assert_eq!(secrets[1].folder, Some("old/work".to_string()));
+        assert_eq!(secrets[2].folder, Some("home".to_string()));
+        assert!(secrets.iter().all(|s| s.tags == vec!["shell".to_string()]));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn ambiguous_query() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let mut test_db = db::DB::new(&conf);
+        for name in ["GitHub", "GitLab"].iter() {
+            test_db
+                .add_secret(test_secret_args(name), HashMap::new())
+                .unwrap();
+        }
+
+        match test_db.get_secret(vec!["git".to_string()], HashMap::new()) {
+            Err(Error::Ambiguous(found)) => {
+                let names: Vec<&str> = found.iter().map(|(_, name)| name.as_str()).collect();
+                assert_eq!(names, vec!["GitHub", "GitLab"]);
+            }
+            other => panic!("Expected ambiguous error, got {:?}", other),
+        }
+        let (value, _) = test_db
+            .get_secret(vec!["name:=github".to_string()], HashMap::new())
+            .unwrap();
+        assert_eq!(value.to_vec(), Vec::from("value"));
+
+        // Same syntax in list
+        let filter = SecretsFilter {
+            tags: vec![],
+            folder: None,
+            query: Some("git -name:lab".to_string()),
+        };
+        let secrets = test_db.list_secrets(&filter).unwrap();
+        assert_eq!(secrets.len(), 1);
+        assert_eq!(secrets[0].name, "GitHub".to_string());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn search_secrets() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let mut test_db = db::DB::new(&conf);
+        for name in ["GitHub", "GitLab", "Mail"].iter() {
+            test_db
+                .add_secret(test_secret_args(name), HashMap::new())
+                .unwrap();
+        }
+
+        let results = test_db.search_secrets("gtl", None).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].secret.name, "GitLab".to_string());
+
+        // Used secret goes first
+        assert_eq!(test_db.search_secrets("git", None).unwrap()[0].secret.name, "GitHub");
+        test_db
+            .get_secret(vec!["name:=gitlab".to_string()], HashMap::new())
+            .unwrap();
+        let results = test_db.search_secrets("git", Some(1)).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].secret.name, "GitLab".to_string());
+
+        // Use is stored with the next save
+        test_db
+            .add_secret(test_secret_args("Other"), HashMap::new())
+            .unwrap();
+        drop(test_db);
+        let mut restarted_db = db::DB::new(&conf);
+        let results = restarted_db.search_secrets("git", None).unwrap();
+        assert_eq!(results[0].secret.name, "GitLab".to_string());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn match_url() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let mut test_db = db::DB::new(&conf);
+        let secrets = [
+            ("Domain", "example.com", UrlMatch::Domain),
+            ("Host", "https://login.example.com", UrlMatch::Host),
+            ("Other", "example.org", UrlMatch::Domain),
+            ("Exact", "https://www.example.com", UrlMatch::Host),
+        ];
+        for (name, url, url_match) in secrets.iter() {
+            let mut args = test_secret_args(name);
+            args.url = Some(url.to_string());
+            args.url_match = *url_match;
+            test_db.add_secret(args, HashMap::new()).unwrap();
+        }
+
+        let candidates = test_db.match_url("https://login.example.com/signin").unwrap();
+        let names: Vec<&str> = candidates.iter().map(|c| c.secret.name.as_str()).collect();
+        assert_eq!(names, vec!["Host", "Domain"]);
+        assert!(test_db.match_url("not a url").is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn get_otp() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let (mut test_db, passwords) = test_db_with_secret(&conf);
+        let mut args = test_secret_args("Bank");
+        args.secret_type = SecretType::Totp;
+        args.value = Some(SecretString::new(
+            "otpauth://totp/Bank:bob?secret=JBSWY3DPEHPK3PXP".to_string(),
+        ));
+        test_db.add_secret(args, passwords.clone()).unwrap();
+
+        let code = test_db
+            .get_otp(vec!["name:=Bank".to_string()], passwords.clone())
+            .unwrap();
+        assert_eq!(code.code.len(), 6);
+        assert!(code.remaining > 0 && code.remaining <= 30);
+        assert_eq!(code.period, 30);
+
+        // Seed is never returned
+        match test_db.get_secret(vec!["name:=Bank".to_string()], passwords.clone()) {
+            Err(Error::InvalidOtp) => (),
+            other => panic!("Expected otp error, got {:?}", other),
+        }
+        assert!(test_db
+            .get_secret_history(vec!["name:=Bank".to_string()], passwords.clone())
+            .is_err());
+
+        // Only for TOTP secrets and with valid keys
+        assert!(test_db.get_otp(vec!["name:=Secret".to_string()], passwords).is_err());
+        assert!(test_db.get_otp(vec!["name:=Bank".to_string()], HashMap::new()).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn add_generated_secret() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let mut test_db = db::DB::new(&conf);
+        let mut args = test_secret_args("Generated");
+        let mut password_args = PasswordArgs::default();
+        password_args.length = 24;
+        args.value = None;
+        args.generate = Some(password_args);
+        test_db.add_secret(args, HashMap::new()).unwrap();
+
+        let (value, _) = test_db
+            .get_secret(vec!["name:=Generated".to_string()], HashMap::new())
+            .unwrap();
+        assert_eq!(value.len(), 24);
+
+        // Only text secrets can be generated
+        let mut args = test_secret_args("File");
+        args.secret_type = SecretType::File;
+        args.value = None;
+        args.generate = Some(PasswordArgs::default());
+        assert!(test_db.add_secret(args, HashMap::new()).is_err());
+
+        // Value should be either provided or generated
+        let mut args = test_secret_args("Both");
+        args.generate = Some(PasswordArgs::default());
+        match test_db.add_secret(args, HashMap::new()) {
+            Err(Error::IncorrectReq) => (),
+            other => panic!("Expected request error, got {:?}", other),
+        }
+        let mut args = test_secret_args("Neither");
+        args.value = None;
+        match test_db.add_secret(args, HashMap::new()) {
+            Err(Error::IncorrectReq) => (),
+            other => panic!("Expected request error, got {:?}", other),
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn audit() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let (mut test_db, passwords) = test_db_with_secret(&conf);
+        test_db
+            .add_secret(test_secret_args("Reused"), passwords.clone())
+            .unwrap();
+
+        // Both secrets have weak "value"
+        let entries = test_db.audit(passwords).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].findings.len(), 2);
+        assert!(test_db.audit(HashMap::new()).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn breached_secrets() {
+        let mut conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let breach_path = format!("{}_breaches", path);
+        // sha1("value")
+        fs::write(&breach_path, "F32B67C7E26342AF42EFABC674D441DCA0A281C5:12\n").unwrap();
+        conf.breach_path = Some(breach_path.clone());
+        let mut test_db = db::DB::new(&conf);
+
+        match test_db.add_secret(test_secret_args("Breached"), HashMap::new()) {
+            Err(Error::Breached(12)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+
+        // Explicitly allowed
+        let mut args = test_secret_args("Breached");
+        args.allow_breached = true;
+        test_db.add_secret(args, HashMap::new()).unwrap();
+        let entries = test_db.audit(HashMap::new()).unwrap();
+        assert!(entries[0]
+            .findings
+            .contains(&audit::Finding::Breached { count: 12 }));
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&breach_path).unwrap();
+    }
+
+    #[test]
+    fn expiry() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let (mut test_db, passwords) = test_db_with_secret(&conf);
+        let mut args = test_secret_args("Rotated");
+        args.expiry_days = Some(1);
+        test_db.add_secret(args, passwords.clone()).unwrap();
+        let mut args = test_secret_args("Nested");
+        args.folder = Some("work/servers".to_string());
+        test_db.add_secret(args, passwords.clone()).unwrap();
+
+        // Expire in a day
+        assert_eq!(test_db.list_expiring(0).unwrap().len(), 0);
+        let expiring = test_db.list_expiring(1).unwrap();
+        assert_eq!(expiring.len(), 1);
+        assert_eq!(expiring[0].secret.name, "Rotated".to_string());
+
+        // Folder policy is stored
+        assert!(test_db.set_folder_expiry("work", Some(1), HashMap::new()).is_err());
+        test_db.set_folder_expiry("/work/", Some(1), passwords.clone()).unwrap();
+        assert_eq!(test_db.list_expiring(1).unwrap().len(), 2);
+        test_db.set_folder_expiry("work", None, passwords.clone()).unwrap();
+        assert_eq!(test_db.list_expiring(1).unwrap().len(), 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn sessions() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let (mut test_db, passwords) = test_db_with_secret(&conf);
+        assert!(test_db.unlock(HashMap::new()).is_err());
+        let token = test_db.unlock(passwords.clone()).unwrap();
+
+        // Token is used instead of passwords
+        let (value, _, _) = test_db
+            .with_session(Some(token.clone()), HashMap::new(), |db, passwords| {
+                db.get_secret_with_fields(vec!["Secret".to_string()], None, passwords)
+            }).unwrap();
+        assert_eq!(value.to_vec(), Vec::from("value"));
+        test_db
+            .with_session(Some(token.clone()), HashMap::new(), |db, passwords| {
+                db.add_secret(test_secret_args("Other"), passwords)
+            }).unwrap();
+        assert!(test_db.session_keys.is_empty());
+
+        // Locked session
+        test_db.lock(&token).unwrap();
+        assert!(test_db.lock(&token).is_err());
+        let res = test_db.with_session(Some(token), HashMap::new(), |db, passwords| {
+            db.get_secret_with_fields(vec!["Other".to_string()], None, passwords)
+        });
+        match res {
+            Err(Error::SessionExpired) => (),
+            _ => panic!("Session should be locked"),
+        }
+
+        // Lock all on idle
+        let token = test_db.unlock(passwords.clone()).unwrap();
+        assert!(!test_db.lock_if_idle(Duration::from_secs(60)));
+        assert!(test_db.lock_if_idle(Duration::from_secs(0)));
+        assert!(test_db.lock(&token).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    /// Change database by other instance as if it was
+    /// other process, releasing locks of database file
+    fn by_other_process<F>(test_db: &mut db::DB, other_db: &mut db::DB, change: F)
+    where
+        F: FnOnce(&mut db::DB),
+    {
+        test_db.lock = None;
+        change(other_db);
+        other_db.lock = None;
+    }
+
+    /// Cache content of database without secrets
+    /// as if file was written long ago
+    fn cache_without_secrets(test_db: &mut db::DB) {
+        test_db.load().unwrap();
+        let stamp = test_db.stamp.take().unwrap();
+        let content = Content {
+            outer_kdf: test_db.outer_kdf,
+            inner_kdf: test_db.inner_kdf,
+            keys: test_db.keys.clone(),
+            secrets: vec![],
+            folder_expiry: HashMap::new(),
+            hash: test_db.hash.clone().unwrap(),
+            base: test_db.base.clone().unwrap(),
+        };
+        test_db.unload();
+        test_db.cache.put(stamp, content, Local::now().timestamp() + 10);
+    }
+
+    #[test]
+    fn cached_content() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let (mut test_db, passwords) = test_db_with_secret(&conf);
+
+        // Just written file isn't cached
+        assert!(test_db.cache.is_empty());
+
+        // Cached content is used while file is the same
+        cache_without_secrets(&mut test_db);
+        test_db.load().unwrap();
+        assert_eq!(test_db.secrets.len(), 0);
+        test_db.unload();
+
+        // File changed by other instance
+        cache_without_secrets(&mut test_db);
+        let mut other_db = db::DB::new(&conf);
+        by_other_process(&mut test_db, &mut other_db, |db| {
+            db.add_secret(test_secret_args("Other"), passwords).unwrap();
+        });
+        test_db.load().unwrap();
+        assert_eq!(test_db.secrets.len(), 2);
+        test_db.unload();
+
+        // Lock drops cache
+        cache_without_secrets(&mut test_db);
+        test_db.lock_all();
+        assert!(test_db.cache.is_empty());
+        test_db.load().unwrap();
+        assert_eq!(test_db.secrets.len(), 2);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    /// Get paths of conflict copies of database
+    fn conflict_copies(path: &str) -> Vec<String> {
+        let dir = Path::new(path).parent().unwrap();
+        let prefix = format!("{}.conflict-", path);
+        fs::read_dir(dir)
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .map(|e| e.path().to_string_lossy().into_owned())
+            .filter(|p| p.starts_with(&prefix))
+            .collect()
+    }
+
+    #[test]
+    fn merge_external_changes() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let (mut test_db, mut passwords) = test_db_with_secret(&conf);
+        let mut other_db = db::DB::new(&conf);
+        let filter = SecretsFilter::default();
+
+        // Changes of different secrets are merged
+        test_db.load().unwrap();
+        test_db.secrets[0].name = "Renamed".to_string();
+        by_other_process(&mut test_db, &mut other_db, |db| {
+            db.add_secret(test_secret_args("Other"), passwords.clone())
+                .unwrap();
+        });
+        test_db.save().unwrap();
+        test_db.unload();
+        let mut names: Vec<String> = test_db
+            .list_secrets(&filter)
+            .unwrap()
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["Other".to_string(), "Renamed".to_string()]);
+        assert!(conflict_copies(&path).is_empty());
+
+        // Secret changed by us and removed by them
+        test_db.load().unwrap();
+        test_db.secrets[0].name = "Ours".to_string();
+        by_other_process(&mut test_db, &mut other_db, |db| {
+            db.rm_secret(vec!["Renamed".to_string()], passwords.clone())
+                .unwrap();
+        });
+        test_db.save().unwrap();
+        test_db.unload();
+        assert_eq!(test_db.list_secrets(&filter).unwrap().len(), 2);
+        let copies = conflict_copies(&path);
+        assert_eq!(copies.len(), 1);
+        fs::remove_file(&copies[0]).unwrap();
+
+        // Secrets can't be merged with added key
+        test_db.load().unwrap();
+        test_db.secrets[0].name = "Ours again".to_string();
+        passwords.insert("Key2".to_string(), SecretString::new("pass2".to_string()));
+        let key = Key::from_args(AddKeyArgs {
+            key_type: KeyType::Text,
+            name: "Key2".to_string(),
+            group: None,
+            value: SecretString::new("pass2".to_string()),
+            passwords: HashMap::new(),
+            token: None,
+        }).unwrap();
+        by_other_process(&mut test_db, &mut other_db, |db| {
+            db.add_key(key, passwords).unwrap();
+        });
+        test_db.save().unwrap();
+        test_db.unload();
+        test_db.load().unwrap();
+        assert_eq!(test_db.keys.len(), 1);
+        test_db.unload();
+        let copies = conflict_copies(&path);
+        assert_eq!(copies.len(), 1);
+        fs::remove_file(&copies[0]).unwrap();
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    /// Latency of requests to 10k secrets database with default
+    /// kdf params. Run with:
+    /// cargo test --release cache_latency -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn cache_latency() {
+        let mut conf = test_conf();
+        conf.scrypt_log2_n = None;
+        conf.scrypt_r = None;
+        conf.scrypt_p = None;
+        let path = conf.db_path.clone().unwrap();
+        let mut test_db = db::DB::new(&conf);
+        test_db.load().unwrap();
+        for i in 0..10000 {
+            let args = test_secret_args(&format!("Secret {}", i));
+            test_db.secrets.push(Secret::from_args(args, None).unwrap());
+        }
+        test_db.save().unwrap();
+        test_db.unload();
+        let filter = SecretsFilter::default();
+
+        // Content is cached only while unlocked
+        test_db.unlock(HashMap::new()).unwrap();
+
+        // Let file stamp become reliable
+        thread::sleep(Duration::from_millis(2100));
+
+        let start = Instant::now();
+        assert_eq!(test_db.list_secrets(&filter).unwrap().len(), 10000);
+        let cold = start.elapsed();
+
+        let start = Instant::now();
+        for _ in 0..10 {
+            assert_eq!(test_db.list_secrets(&filter).unwrap().len(), 10000);
+        }
+        let warm = start.elapsed() / 10;
+
+        println!("list-secrets, 10k secrets: cold {:?}, cached {:?}", cold, warm);
+        assert!(warm < cold);
+
+        fs::remove_file(&path).unwrap();
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn locked_db() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let (mut test_db, passwords) = test_db_with_secret(&conf);
+
+        // Second daemon
+        let mut other_db = db::DB::new(&conf);
+        match other_db.load() {
+            Err(Error::Locked(Some(pid))) => assert_eq!(pid, process::id()),
+            other => panic!("Expected lock error, got {:?}", other),
+        }
+
+        // Read-only daemons share database
+        let mut ro_conf = conf.clone();
+        ro_conf.read_only = Some(true);
+        let mut ro_db = db::DB::new(&ro_conf);
+        assert!(ro_db.load().is_err());
+        drop(test_db);
+        let mut other_ro_db = db::DB::new(&ro_conf);
+        ro_db.load().unwrap();
+        other_ro_db.load().unwrap();
+        assert_eq!(ro_db.secrets.len(), 1);
+        match ro_db.add_secret(test_secret_args("Other"), passwords) {
+            Err(Error::ReadOnly) => (),
+            other => panic!("Expected read-only error, got {:?}", other),
+        }
+        match other_db.load() {
+            Err(Error::LockedShared) => (),
+            other => panic!("Expected lock error, got {:?}", other),
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tampered_db() {
+        let conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let mut test_db = db::DB::new(&conf);
+        test_db
+            .add_secret(test_secret_args("Secret"), HashMap::new())
+            .unwrap();
+
+        // Flip one byte of ciphertext
+        let mut data = fs::read(&path).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0x01;
+        fs::write(&path, &data).unwrap();
+
+        match test_db.load() {
+            Err(Error::Tampered) => (),
+            other => panic!("Expected tamper error, got {:?}", other),
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn wrong_db_key() {
+        let mut conf = test_conf();
+        let path = conf.db_path.clone().unwrap();
+        let mut test_db = db::DB::new(&conf);
+        test_db
+            .add_secret(test_secret_args("Secret"), HashMap::new())
+            .unwrap();
+        drop(test_db);
+
+        // Wrong key can't be told from modified file
+        conf.db_key = Some("wrong".to_string());
+        let mut test_db = db::DB::new(&conf);
+        match test_db.load() {
+            Err(Error::Tampered) => (),
+            other => panic!("Expected tamper error, got {:?}", other),
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+}
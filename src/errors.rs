@@ -26,9 +26,22 @@ pub enum Error {
     IncorrectConf,
     IncorrectReq,
     IncorrectOuterKey,
+    /// Authentication of database file failed. GCM can't tell
+    /// a modified file from a wrong db key, so both end here.
+    Tampered,
     Duplicate,
     InvalidKey,
     NotEnoughKeys,
+    InvalidQuery,
+    InvalidUrl,
+    InvalidOtp,
+    Breached(u64),
+    SessionExpired,
+    Locked(Option<u32>),
+    LockedShared,
+    ReadOnly,
+    /// Ids and names of matched secrets
+    Ambiguous(Vec<(String, String)>),
     Unknown,
 }
 
@@ -52,9 +65,19 @@ impl Error {
             Error::IncorrectConf => "incorrect-config",
             Error::IncorrectReq => "incorrect-request",
             Error::IncorrectOuterKey => "incorrect-outer-key",
+            Error::Tampered => "tampered",
             Error::Duplicate => "duplicate",
             Error::InvalidKey => "invalid-key",
             Error::NotEnoughKeys => "not-enough-keys",
+            Error::InvalidQuery => "invalid-query",
+            Error::InvalidUrl => "invalid-url",
+            Error::InvalidOtp => "invalid-otp",
+            Error::Breached(_) => "breached",
+            Error::SessionExpired => "session-expired",
+            Error::Locked(_) => "locked",
+            Error::LockedShared => "locked-shared",
+            Error::ReadOnly => "read-only",
+            Error::Ambiguous(_) => "ambiguous",
             _ => "unknown",
         }
     }
@@ -85,9 +108,26 @@ impl Error {
             Error::IncorrectConf => "incorrenct-config".to_string(),
             Error::IncorrectReq => "incorrenct-request".to_string(),
             Error::IncorrectOuterKey => "incorrect-outer-key".to_string(),
+            Error::Tampered => "tampered".to_string(),
             Error::Duplicate => "duplicate".to_string(),
             Error::InvalidKey => "invalid-key".to_string(),
             Error::NotEnoughKeys => "not-enough-keys".to_string(),
+            Error::InvalidQuery => "invalid-query".to_string(),
+            Error::InvalidUrl => "invalid-url".to_string(),
+            Error::InvalidOtp => "invalid-otp".to_string(),
+            Error::Breached(count) => format!("breached-{}", count),
+            Error::SessionExpired => "session-expired".to_string(),
+            Error::Locked(Some(pid)) => format!("locked-{}", pid),
+            Error::Locked(None) => "locked".to_string(),
+            Error::LockedShared => "locked-shared".to_string(),
+            Error::ReadOnly => "read-only".to_string(),
+            Error::Ambiguous(found) => {
+                let found: Vec<String> = found
+                    .iter()
+                    .map(|(id, name)| format!("{}:{}", id, name))
+                    .collect();
+                "ambiguous-".to_string() + &found.join(",")
+            }
             _ => "unknown".to_string(),
         }
     }
@@ -113,9 +153,26 @@ impl fmt::Display for Error {
             Error::IncorrectConf => write!(f, "Incorrect config"),
             Error::IncorrectReq => write!(f, "Incorrect request"),
             Error::IncorrectOuterKey => write!(f, "Incorrect outer key"),
+            Error::Tampered => write!(f, "Wrong db key or modified database"),
             Error::Duplicate => write!(f, "Duplicate"),
             Error::InvalidKey => write!(f, "Invalid key"),
             Error::NotEnoughKeys => write!(f, "Not enough keys"),
+            Error::InvalidQuery => write!(f, "Invalid query"),
+            Error::InvalidUrl => write!(f, "Invalid url"),
+            Error::InvalidOtp => write!(f, "Invalid one-time password secret"),
+            Error::Breached(count) => write!(f, "Password was found in {} breaches", count),
+            Error::SessionExpired => write!(f, "Session expired or locked"),
+            Error::Locked(Some(pid)) => write!(f, "Database is used by process {}", pid),
+            Error::Locked(None) => write!(f, "Database is used by another process"),
+            Error::LockedShared => write!(f, "Database is used by read-only processes"),
+            Error::ReadOnly => write!(f, "Database is opened read-only"),
+            Error::Ambiguous(found) => {
+                let found: Vec<String> = found
+                    .iter()
+                    .map(|(id, name)| format!("{} ({})", name, id))
+                    .collect();
+                write!(f, "Ambiguous query: {}", found.join(", "))
+            }
             _ => write!(f, "Unknown error"),
         }
     }
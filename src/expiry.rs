@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use secret::{Secret, SecretInfo};
+
+/// Seconds in one day
+const DAY: i64 = 86400;
+
+/// Secret that should be rotated
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpiringSecret {
+    pub secret: SecretInfo,
+    /// Time when value expires
+    pub expires: i64,
+    pub overdue: bool,
+}
+
+/// Get rotation period (days) of secret: its own one
+/// or policy of the nearest folder
+pub fn period(secret: &Secret, folder_expiry: &HashMap<String, u32>) -> Option<u32> {
+    if secret.expiry_days.is_some() {
+        return secret.expiry_days;
+    }
+
+    let mut folder = match secret.folder {
+        Some(ref f) => f.as_str(),
+        None => return None,
+    };
+    loop {
+        if let Some(days) = folder_expiry.get(folder) {
+            return Some(*days);
+        }
+        match folder.rfind('/') {
+            Some(i) => folder = &folder[..i],
+            None => return None,
+        }
+    }
+}
+
+/// Get time when value of secret expires
+pub fn expires(secret: &Secret, folder_expiry: &HashMap<String, u32>) -> Option<i64> {
+    period(secret, folder_expiry).map(|days| secret.value_date() + days as i64 * DAY)
+}
+
+/// Get secrets expiring before now + within (seconds),
+/// the earliest first
+pub fn expiring(
+    secrets: &[Secret],
+    folder_expiry: &HashMap<String, u32>,
+    now: i64,
+    within: i64,
+) -> Vec<ExpiringSecret> {
+    let mut expiring: Vec<ExpiringSecret> = secrets
+        .iter()
+        .filter_map(|s| {
+            let expires = expires(s, folder_expiry)?;
+            match expires <= now + within {
+                true => Some(ExpiringSecret {
+                    secret: s.into(),
+                    expires: expires,
+                    overdue: expires <= now,
+                }),
+                false => None,
+            }
+        })
+        .collect();
+
+    expiring.sort_by(|a, b| a.expires.cmp(&b.expires).then(a.secret.name.cmp(&b.secret.name)));
+    expiring
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use expiry::*;
+    use secret::{self, normalize_folder, Secret};
+
+    fn test_secret(name: &str, folder: Option<&str>, expiry_days: Option<u32>) -> Secret {
+        let mut secret = secret::test_secret(name);
+        secret.date = 0;
+        secret.folder = folder.and_then(normalize_folder);
+        secret.expiry_days = expiry_days;
+        secret
+    }
+
+    #[test]
+    fn policies() {
+        let mut folder_expiry = HashMap::new();
+        folder_expiry.insert("work".to_string(), 90);
+        folder_expiry.insert("work/servers".to_string(), 30);
+
+        let own = test_secret("Own", Some("work"), Some(10));
+        let nested = test_secret("Nested", Some("work/servers/db"), None);
+        let folder = test_secret("Folder", Some("work/mail"), None);
+        let none = test_secret("None", Some("home"), None);
+
+        assert_eq!(period(&own, &folder_expiry), Some(10));
+        assert_eq!(period(&nested, &folder_expiry), Some(30));
+        assert_eq!(period(&folder, &folder_expiry), Some(90));
+        assert_eq!(period(&none, &folder_expiry), None);
+
+        let secrets = vec![own, nested, folder, none];
+        let due = expiring(&secrets, &folder_expiry, 30 * DAY, 0);
+        assert_eq!(due.len(), 2);
+        assert_eq!(due[0].secret.name, "Own".to_string());
+        assert!(due[0].overdue);
+        assert_eq!(due[1].expires, 30 * DAY);
+
+        // Expiring in 60 days
+        let soon = expiring(&secrets, &folder_expiry, 30 * DAY, 60 * DAY);
+        assert_eq!(soon.len(), 3);
+        assert!(!soon[2].overdue);
+    }
+}
@@ -44,6 +44,7 @@ pub fn add_key_handler<T>(msg: Msg, _: SharedState<T>, shared_db: Arc<Mutex<DB>>
         Err(e) => return AddKeyAns::json(Some(Error::JSON(e))),
     };
     let text_keys = key_args.passwords.clone();
+    let token = key_args.token.clone();
 
     // Unlock database
     let mut db = match shared_db.lock() {
@@ -58,7 +59,9 @@ pub fn add_key_handler<T>(msg: Msg, _: SharedState<T>, shared_db: Arc<Mutex<DB>>
     };
 
     // Add new key
-    match db.add_key(key, text_keys) {
+    match db.with_session(token, text_keys, |db, passwords| {
+        db.add_key(key, passwords)
+    }) {
         Ok(_) => AddKeyAns::json(None),
         Err(err) => AddKeyAns::json(Some(err)),
     }
@@ -48,13 +48,16 @@ pub fn add_secret_handler<T>(
         Err(e) => return AddSecretAns::json(Some(Error::JSON(e))),
     };
     let passwords = args.passwords.clone();
+    let token = args.token.clone();
 
     let mut db = match shared_db.lock() {
         Ok(db) => db,
         Err(_) => return AddSecretAns::json(Some(Error::Internal)),
     };
 
-    match db.add_secret(args, passwords) {
+    match db.with_session(token, passwords, |db, passwords| {
+        db.add_secret(args, passwords)
+    }) {
         Ok(_) => AddSecretAns::json(None),
         Err(err) => AddSecretAns::json(Some(err)),
     }
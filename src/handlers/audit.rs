@@ -0,0 +1,80 @@
+use std::sync::{Arc, Mutex};
+
+use audit::AuditEntry;
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+use key::Passwords;
+
+#[derive(Debug, Clone, Deserialize)]
+struct AuditArgs {
+    #[serde(default)]
+    pub passwords: Passwords,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct AuditAns {
+    entries: Vec<AuditEntry>,
+    error: Option<String>,
+}
+
+impl AuditAns {
+    pub fn json(entries: Option<Vec<AuditEntry>>, err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = AuditAns {
+            entries: vec![],
+            error: None,
+        };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Put entries
+        if let Some(entries) = entries {
+            ans.entries = entries;
+        }
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return AuditAns::json(None, Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn audit_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let args_json = match msg.body {
+        Some(body) => body,
+        None => return AuditAns::json(None, Some(Error::IncorrectReq)),
+    };
+
+    // Parse json
+    let args: AuditArgs = match serde_json::from_slice(&args_json) {
+        Ok(a) => a,
+        Err(e) => return AuditAns::json(None, Some(Error::JSON(e))),
+    };
+
+    // Lock database
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return AuditAns::json(None, Some(Error::Internal)),
+    };
+
+    match db.with_session(args.token, args.passwords, |db, passwords| db.audit(passwords)) {
+        Ok(entries) => return AuditAns::json(Some(entries), None),
+        Err(err) => return AuditAns::json(None, Some(err)),
+    }
+}
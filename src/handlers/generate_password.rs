@@ -0,0 +1,73 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+use password::{Password, PasswordArgs};
+use secure::SecretString;
+
+#[derive(Debug, Clone, Serialize)]
+struct GeneratePasswordAns {
+    value: Option<SecretString>,
+    entropy: Option<f64>,
+    error: Option<String>,
+}
+
+impl GeneratePasswordAns {
+    pub fn json(password: Option<Password>, err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = GeneratePasswordAns {
+            value: None,
+            entropy: None,
+            error: None,
+        };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Put password
+        if let Some(password) = password {
+            ans.value = Some(password.value);
+            ans.entropy = Some(password.entropy);
+        }
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return GeneratePasswordAns::json(None, Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn generate_password_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    // Parse json (all args are optional)
+    let args: PasswordArgs = match msg.body {
+        Some(body) => match serde_json::from_slice(&body) {
+            Ok(a) => a,
+            Err(e) => return GeneratePasswordAns::json(None, Some(Error::JSON(e))),
+        },
+        None => PasswordArgs::default(),
+    };
+
+    // Lock database
+    let db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return GeneratePasswordAns::json(None, Some(Error::Internal)),
+    };
+
+    match db.generate_password(&args) {
+        Ok(password) => return GeneratePasswordAns::json(Some(password), None),
+        Err(err) => return GeneratePasswordAns::json(None, Some(err)),
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+use key::Passwords;
+use otp::OtpCode;
+
+#[derive(Debug, Clone, Deserialize)]
+struct GetOtpArgs {
+    pub query: Vec<String>,
+    #[serde(default)]
+    pub passwords: Passwords,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct GetOtpAns {
+    code: Option<String>,
+    remaining: Option<u64>,
+    period: Option<u64>,
+    error: Option<String>,
+}
+
+impl GetOtpAns {
+    pub fn json(code: Option<OtpCode>, err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = GetOtpAns {
+            code: None,
+            remaining: None,
+            period: None,
+            error: None,
+        };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Put code
+        if let Some(code) = code {
+            ans.code = Some(code.code);
+            ans.remaining = Some(code.remaining);
+            ans.period = Some(code.period);
+        }
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return GetOtpAns::json(None, Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn get_otp_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let args_json = match msg.body {
+        Some(body) => body,
+        None => return GetOtpAns::json(None, Some(Error::IncorrectReq)),
+    };
+
+    // Parse json
+    let args: GetOtpArgs = match serde_json::from_slice(&args_json) {
+        Ok(a) => a,
+        Err(e) => return GetOtpAns::json(None, Some(Error::JSON(e))),
+    };
+    let passwords = args.passwords.clone();
+    let token = args.token.clone();
+
+    // Lock database
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return GetOtpAns::json(None, Some(Error::Internal)),
+    };
+
+    match db.with_session(token, passwords, |db, passwords| {
+        db.get_otp(args.query, passwords)
+    }) {
+        Ok(code) => return GetOtpAns::json(Some(code), None),
+        Err(err) => return GetOtpAns::json(None, Some(err)),
+    }
+}
@@ -5,31 +5,40 @@ use con::Msg;
 use db::DB;
 use errors::Error;
 use key::Passwords;
-use secret::SecretType;
+use secret::{FieldValue, SecretType};
+use secure::SecretBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct GetSecretArgs {
     pub query: Vec<String>,
+    #[serde(default)]
+    pub field: Option<String>,
+    #[serde(default)]
     pub passwords: Passwords,
+    #[serde(default)]
+    pub token: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 struct GetSecretAns {
-    secret: Option<Vec<u8>>,
+    secret: Option<SecretBuf>,
     #[serde(rename = "type")]
     secret_type: Option<SecretType>,
+    fields: Vec<FieldValue>,
     error: Option<String>,
 }
 
 impl GetSecretAns {
     pub fn json(
-        secret: Option<Vec<u8>>,
+        secret: Option<SecretBuf>,
         secret_type: Option<SecretType>,
+        fields: Vec<FieldValue>,
         err: Option<Error>,
     ) -> Option<Vec<u8>> {
         let mut ans = GetSecretAns {
             secret: secret,
             secret_type: secret_type,
+            fields: fields,
             error: None,
         };
 
@@ -44,7 +53,7 @@ impl GetSecretAns {
         // Serialize
         let json = match serde_json::to_vec(&ans) {
             Ok(v) => v,
-            Err(e) => return GetSecretAns::json(None, None, Some(Error::JSON(e))),
+            Err(e) => return GetSecretAns::json(None, None, vec![], Some(Error::JSON(e))),
         };
 
         Some(json)
@@ -58,24 +67,27 @@ pub fn get_secret_handler<T>(
 ) -> Option<Vec<u8>> {
     let args_json = match msg.body {
         Some(body) => body,
-        None => return GetSecretAns::json(None, None, Some(Error::IncorrectReq)),
+        None => return GetSecretAns::json(None, None, vec![], Some(Error::IncorrectReq)),
     };
 
     // Parse json
     let args: GetSecretArgs = match serde_json::from_slice(&args_json) {
         Ok(k) => k,
-        Err(e) => return GetSecretAns::json(None, None, Some(Error::JSON(e))),
+        Err(e) => return GetSecretAns::json(None, None, vec![], Some(Error::JSON(e))),
     };
     let passwords = args.passwords.clone();
+    let token = args.token.clone();
 
     // Lock database
     let mut db = match shared_db.lock() {
         Ok(db) => db,
-        Err(_) => return GetSecretAns::json(None, None, Some(Error::Internal)),
+        Err(_) => return GetSecretAns::json(None, None, vec![], Some(Error::Internal)),
     };
 
-    match db.get_secret(args.query, passwords) {
-        Ok((v, t)) => return GetSecretAns::json(Some(v), Some(t), None),
-        Err(err) => return GetSecretAns::json(None, None, Some(err)),
+    match db.with_session(token, passwords, |db, passwords| {
+        db.get_secret_with_fields(args.query, args.field, passwords)
+    }) {
+        Ok((v, t, f)) => return GetSecretAns::json(Some(v), Some(t), f, None),
+        Err(err) => return GetSecretAns::json(None, None, vec![], Some(err)),
     }
 }
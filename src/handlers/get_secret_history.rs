@@ -0,0 +1,85 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+use key::Passwords;
+use secret::HistoryValue;
+
+#[derive(Debug, Clone, Deserialize)]
+struct GetSecretHistoryArgs {
+    pub query: Vec<String>,
+    #[serde(default)]
+    pub passwords: Passwords,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct GetSecretHistoryAns {
+    history: Vec<HistoryValue>,
+    error: Option<String>,
+}
+
+impl GetSecretHistoryAns {
+    pub fn json(history: Option<Vec<HistoryValue>>, err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = GetSecretHistoryAns {
+            history: vec![],
+            error: None,
+        };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Put history
+        if let Some(history) = history {
+            ans.history = history;
+        }
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return GetSecretHistoryAns::json(None, Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn get_secret_history_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let args_json = match msg.body {
+        Some(body) => body,
+        None => return GetSecretHistoryAns::json(None, Some(Error::IncorrectReq)),
+    };
+
+    // Parse json
+    let args: GetSecretHistoryArgs = match serde_json::from_slice(&args_json) {
+        Ok(a) => a,
+        Err(e) => return GetSecretHistoryAns::json(None, Some(Error::JSON(e))),
+    };
+    let passwords = args.passwords.clone();
+    let token = args.token.clone();
+
+    // Lock database
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return GetSecretHistoryAns::json(None, Some(Error::Internal)),
+    };
+
+    match db.with_session(token, passwords, |db, passwords| {
+        db.get_secret_history(args.query, passwords)
+    }) {
+        Ok(history) => return GetSecretHistoryAns::json(Some(history), None),
+        Err(err) => return GetSecretHistoryAns::json(None, Some(err)),
+    }
+}
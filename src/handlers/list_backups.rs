@@ -0,0 +1,58 @@
+use backup::BackupInfo;
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+use std::sync::{Arc, Mutex};
+
+#[derive(Serialize)]
+struct ListBackupsAns {
+    backups: Vec<BackupInfo>,
+    error: Option<String>,
+}
+
+impl ListBackupsAns {
+    pub fn json(backups: Option<Vec<BackupInfo>>, err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = ListBackupsAns {
+            backups: vec![],
+            error: None,
+        };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Put backups
+        if let Some(backups) = backups {
+            ans.backups = backups;
+        }
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return ListBackupsAns::json(None, Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn list_backups_handler<T>(
+    _: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return ListBackupsAns::json(None, Some(Error::Internal)),
+    };
+
+    match db.list_backups() {
+        Ok(backups) => return ListBackupsAns::json(Some(backups), None),
+        Err(err) => return ListBackupsAns::json(None, Some(err)),
+    }
+}
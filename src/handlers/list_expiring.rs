@@ -0,0 +1,76 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+use expiry::ExpiringSecret;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct ListExpiringArgs {
+    /// Include secrets expiring in this number of days
+    #[serde(default)]
+    pub within_days: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ListExpiringAns {
+    expiring: Vec<ExpiringSecret>,
+    error: Option<String>,
+}
+
+impl ListExpiringAns {
+    pub fn json(expiring: Option<Vec<ExpiringSecret>>, err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = ListExpiringAns {
+            expiring: vec![],
+            error: None,
+        };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Put secrets
+        if let Some(expiring) = expiring {
+            ans.expiring = expiring;
+        }
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return ListExpiringAns::json(None, Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn list_expiring_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    // Parse json (only overdue secrets by default)
+    let args: ListExpiringArgs = match msg.body {
+        Some(body) => match serde_json::from_slice(&body) {
+            Ok(a) => a,
+            Err(e) => return ListExpiringAns::json(None, Some(Error::JSON(e))),
+        },
+        None => ListExpiringArgs::default(),
+    };
+
+    // Lock database
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return ListExpiringAns::json(None, Some(Error::Internal)),
+    };
+
+    match db.list_expiring(args.within_days) {
+        Ok(expiring) => return ListExpiringAns::json(Some(expiring), None),
+        Err(err) => return ListExpiringAns::json(None, Some(err)),
+    }
+}
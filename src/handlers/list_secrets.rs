@@ -2,7 +2,7 @@ use con::server::SharedState;
 use con::Msg;
 use db::DB;
 use errors::Error;
-use secret::{SecretInfo};
+use secret::{SecretInfo, SecretsFilter};
 use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Clone, Serialize)]
@@ -42,17 +42,26 @@ impl ListSecretsAns {
 }
 
 pub fn list_secrets_handler<T>(
-    _: Msg,
+    msg: Msg,
     _: SharedState<T>,
     shared_db: Arc<Mutex<DB>>,
 ) -> Option<Vec<u8>> {
+    // Parse optional filter
+    let filter: SecretsFilter = match msg.body {
+        Some(body) => match serde_json::from_slice(&body) {
+            Ok(f) => f,
+            Err(e) => return ListSecretsAns::json(None, Some(Error::JSON(e))),
+        },
+        None => SecretsFilter::default(),
+    };
+
     // Lock database
     let mut db = match shared_db.lock() {
         Ok(db) => db,
         Err(_) => return ListSecretsAns::json(None, Some(Error::Internal)),
     };
 
-    match db.list_secrets() {
+    match db.list_secrets(&filter) {
         Ok(secrets) => return ListSecretsAns::json(Some(secrets), None),
         Err(err) => return ListSecretsAns::json(None, Some(err)),
     }
@@ -0,0 +1,63 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+
+#[derive(Debug, Deserialize)]
+struct LockArgs {
+    token: String,
+}
+
+#[derive(Serialize)]
+struct LockAns {
+    error: Option<String>,
+}
+
+impl LockAns {
+    pub fn json(err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = LockAns { error: None };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return LockAns::json(Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn lock_handler<T>(msg: Msg, _: SharedState<T>, shared_db: Arc<Mutex<DB>>) -> Option<Vec<u8>> {
+    let args_json = match msg.body {
+        Some(body) => body,
+        None => return LockAns::json(Some(Error::IncorrectReq)),
+    };
+
+    // Parse json
+    let args: LockArgs = match serde_json::from_slice(&args_json) {
+        Ok(k) => k,
+        Err(e) => return LockAns::json(Some(Error::JSON(e))),
+    };
+
+    // Close session
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return LockAns::json(Some(Error::Internal)),
+    };
+    match db.lock(&args.token) {
+        Ok(_) => (),
+        Err(err) => return LockAns::json(Some(err)),
+    }
+
+    LockAns::json(None)
+}
@@ -0,0 +1,47 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+
+#[derive(Serialize)]
+struct LockAllAns {
+    error: Option<String>,
+}
+
+impl LockAllAns {
+    pub fn json(err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = LockAllAns { error: None };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return LockAllAns::json(Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn lock_all_handler<T>(
+    _: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return LockAllAns::json(Some(Error::Internal)),
+    };
+    db.lock_all();
+
+    LockAllAns::json(None)
+}
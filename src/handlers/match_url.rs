@@ -0,0 +1,75 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::{UrlCandidate, DB};
+use errors::Error;
+
+#[derive(Debug, Clone, Deserialize)]
+struct MatchUrlArgs {
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct MatchUrlAns {
+    candidates: Vec<UrlCandidate>,
+    error: Option<String>,
+}
+
+impl MatchUrlAns {
+    pub fn json(candidates: Option<Vec<UrlCandidate>>, err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = MatchUrlAns {
+            candidates: vec![],
+            error: None,
+        };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Put candidates
+        if let Some(candidates) = candidates {
+            ans.candidates = candidates;
+        }
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return MatchUrlAns::json(None, Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn match_url_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let args_json = match msg.body {
+        Some(body) => body,
+        None => return MatchUrlAns::json(None, Some(Error::IncorrectReq)),
+    };
+
+    // Parse json
+    let args: MatchUrlArgs = match serde_json::from_slice(&args_json) {
+        Ok(a) => a,
+        Err(e) => return MatchUrlAns::json(None, Some(Error::JSON(e))),
+    };
+
+    // Lock database
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return MatchUrlAns::json(None, Some(Error::Internal)),
+    };
+
+    match db.match_url(&args.url) {
+        Ok(candidates) => return MatchUrlAns::json(Some(candidates), None),
+        Err(err) => return MatchUrlAns::json(None, Some(err)),
+    }
+}
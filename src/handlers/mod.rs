@@ -5,3 +5,23 @@ pub mod remove_secret;
 pub mod list_keys;
 pub mod list_secrets;
 pub mod get_secret;
+pub mod rekey_kdf;
+pub mod list_backups;
+pub mod restore_backup;
+pub mod update_secret;
+pub mod get_secret_history;
+pub mod restore_secret_history;
+pub mod rename_tag;
+pub mod move_folder;
+pub mod search_secrets;
+pub mod match_url;
+pub mod get_otp;
+pub mod generate_password;
+pub mod audit;
+pub mod list_expiring;
+pub mod set_folder_expiry;
+pub mod subscribe_events;
+pub mod unsubscribe_events;
+pub mod unlock;
+pub mod lock;
+pub mod lock_all;
@@ -0,0 +1,77 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+use key::Passwords;
+
+#[derive(Debug, Deserialize)]
+struct MoveFolderArgs {
+    from: String,
+    to: String,
+    #[serde(default)]
+    passwords: Passwords,
+    #[serde(default)]
+    token: Option<String>,
+}
+
+#[derive(Serialize)]
+struct MoveFolderAns {
+    error: Option<String>,
+}
+
+impl MoveFolderAns {
+    pub fn json(err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = MoveFolderAns { error: None };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return MoveFolderAns::json(Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn move_folder_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let args_json = match msg.body {
+        Some(body) => body,
+        None => return MoveFolderAns::json(Some(Error::IncorrectReq)),
+    };
+
+    // Parse json
+    let args: MoveFolderArgs = match serde_json::from_slice(&args_json) {
+        Ok(k) => k,
+        Err(e) => return MoveFolderAns::json(Some(Error::JSON(e))),
+    };
+    let passwords = args.passwords.clone();
+    let token = args.token.clone();
+
+    // Move folder
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return MoveFolderAns::json(Some(Error::Internal)),
+    };
+    match db.with_session(token, passwords, |db, passwords| {
+        db.move_folder(&args.from, &args.to, passwords)
+    }) {
+        Ok(_) => (),
+        Err(err) => return MoveFolderAns::json(Some(err)),
+    }
+
+    MoveFolderAns::json(None)
+}
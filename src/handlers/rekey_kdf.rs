@@ -0,0 +1,73 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+use key::Passwords;
+
+#[derive(Debug, Deserialize)]
+struct RekeyKdfArgs {
+    #[serde(default)]
+    passwords: Passwords,
+    #[serde(default)]
+    token: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RekeyKdfAns {
+    error: Option<String>,
+}
+
+impl RekeyKdfAns {
+    pub fn json(err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = RekeyKdfAns { error: None };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return RekeyKdfAns::json(Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn rekey_kdf_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let args_json = match msg.body {
+        Some(body) => body,
+        None => return RekeyKdfAns::json(Some(Error::IncorrectReq)),
+    };
+
+    // Parse json
+    let args: RekeyKdfArgs = match serde_json::from_slice(&args_json) {
+        Ok(k) => k,
+        Err(e) => return RekeyKdfAns::json(Some(Error::JSON(e))),
+    };
+    let passwords = args.passwords.clone();
+    let token = args.token.clone();
+
+    // Rekey database
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return RekeyKdfAns::json(Some(Error::Internal)),
+    };
+    match db.with_session(token, passwords, |db, passwords| db.rekey_kdf(passwords)) {
+        Ok(_) => (),
+        Err(err) => return RekeyKdfAns::json(Some(err)),
+    }
+
+    RekeyKdfAns::json(None)
+}
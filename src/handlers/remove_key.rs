@@ -9,7 +9,10 @@ use key::Passwords;
 #[derive(Debug, Deserialize)]
 struct RemoveKeyArgs {
     name: String,
+    #[serde(default)]
     passwords: Passwords,
+    #[serde(default)]
+    token: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -55,13 +58,16 @@ pub fn remove_key_handler<T>(
         Err(e) => return RemoveKeyAns::json(Some(Error::JSON(e))),
     };
     let passwords = args.passwords.clone();
+    let token = args.token.clone();
 
     // Remove key
     let mut db = match shared_db.lock() {
         Ok(db) => db,
         Err(_) => return RemoveKeyAns::json(Some(Error::Internal)),
     };
-    match db.rm_key(&args.name, passwords) {
+    match db.with_session(token, passwords, |db, passwords| {
+        db.rm_key(&args.name, passwords)
+    }) {
         Ok(_) => (),
         Err(err) => return RemoveKeyAns::json(Some(err)),
     }
@@ -9,7 +9,10 @@ use key::Passwords;
 #[derive(Debug, Deserialize)]
 struct RemoveSecretArgs {
     query: Vec<String>,
+    #[serde(default)]
     passwords: Passwords,
+    #[serde(default)]
+    token: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -55,13 +58,16 @@ pub fn remove_secret_handler<T>(
         Err(e) => return RemoveSecretAns::json(Some(Error::JSON(e))),
     };
     let passwords = args.passwords.clone();
+    let token = args.token.clone();
 
     // Remove key
     let mut db = match shared_db.lock() {
         Ok(db) => db,
         Err(_) => return RemoveSecretAns::json(Some(Error::Internal)),
     };
-    match db.rm_secret(args.query, passwords) {
+    match db.with_session(token, passwords, |db, passwords| {
+        db.rm_secret(args.query, passwords)
+    }) {
         Ok(_) => (),
         Err(err) => return RemoveSecretAns::json(Some(err)),
     }
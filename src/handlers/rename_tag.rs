@@ -0,0 +1,77 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+use key::Passwords;
+
+#[derive(Debug, Deserialize)]
+struct RenameTagArgs {
+    from: String,
+    to: String,
+    #[serde(default)]
+    passwords: Passwords,
+    #[serde(default)]
+    token: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RenameTagAns {
+    error: Option<String>,
+}
+
+impl RenameTagAns {
+    pub fn json(err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = RenameTagAns { error: None };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return RenameTagAns::json(Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn rename_tag_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let args_json = match msg.body {
+        Some(body) => body,
+        None => return RenameTagAns::json(Some(Error::IncorrectReq)),
+    };
+
+    // Parse json
+    let args: RenameTagArgs = match serde_json::from_slice(&args_json) {
+        Ok(k) => k,
+        Err(e) => return RenameTagAns::json(Some(Error::JSON(e))),
+    };
+    let passwords = args.passwords.clone();
+    let token = args.token.clone();
+
+    // Rename tag
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return RenameTagAns::json(Some(Error::Internal)),
+    };
+    match db.with_session(token, passwords, |db, passwords| {
+        db.rename_tag(&args.from, &args.to, passwords)
+    }) {
+        Ok(_) => (),
+        Err(err) => return RenameTagAns::json(Some(err)),
+    }
+
+    RenameTagAns::json(None)
+}
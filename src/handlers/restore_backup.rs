@@ -0,0 +1,67 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+
+#[derive(Debug, Deserialize)]
+struct RestoreBackupArgs {
+    name: String,
+}
+
+#[derive(Serialize)]
+struct RestoreBackupAns {
+    error: Option<String>,
+}
+
+impl RestoreBackupAns {
+    pub fn json(err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = RestoreBackupAns { error: None };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return RestoreBackupAns::json(Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn restore_backup_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let args_json = match msg.body {
+        Some(body) => body,
+        None => return RestoreBackupAns::json(Some(Error::IncorrectReq)),
+    };
+
+    // Parse json
+    let args: RestoreBackupArgs = match serde_json::from_slice(&args_json) {
+        Ok(a) => a,
+        Err(e) => return RestoreBackupAns::json(Some(Error::JSON(e))),
+    };
+
+    // Restore backup
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return RestoreBackupAns::json(Some(Error::Internal)),
+    };
+    match db.restore_backup(&args.name) {
+        Ok(_) => (),
+        Err(err) => return RestoreBackupAns::json(Some(err)),
+    }
+
+    RestoreBackupAns::json(None)
+}
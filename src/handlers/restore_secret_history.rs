@@ -0,0 +1,77 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+use key::Passwords;
+
+#[derive(Debug, Deserialize)]
+struct RestoreSecretHistoryArgs {
+    query: Vec<String>,
+    index: usize,
+    #[serde(default)]
+    passwords: Passwords,
+    #[serde(default)]
+    token: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RestoreSecretHistoryAns {
+    error: Option<String>,
+}
+
+impl RestoreSecretHistoryAns {
+    pub fn json(err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = RestoreSecretHistoryAns { error: None };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return RestoreSecretHistoryAns::json(Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn restore_secret_history_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let args_json = match msg.body {
+        Some(body) => body,
+        None => return RestoreSecretHistoryAns::json(Some(Error::IncorrectReq)),
+    };
+
+    // Parse json
+    let args: RestoreSecretHistoryArgs = match serde_json::from_slice(&args_json) {
+        Ok(a) => a,
+        Err(e) => return RestoreSecretHistoryAns::json(Some(Error::JSON(e))),
+    };
+    let passwords = args.passwords.clone();
+    let token = args.token.clone();
+
+    // Restore value
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return RestoreSecretHistoryAns::json(Some(Error::Internal)),
+    };
+    match db.with_session(token, passwords, |db, passwords| {
+        db.restore_secret_history(args.query, args.index, passwords)
+    }) {
+        Ok(_) => (),
+        Err(err) => return RestoreSecretHistoryAns::json(Some(err)),
+    }
+
+    RestoreSecretHistoryAns::json(None)
+}
@@ -0,0 +1,77 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+use search::SearchResult;
+
+#[derive(Debug, Clone, Deserialize)]
+struct SearchSecretsArgs {
+    pub query: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SearchSecretsAns {
+    results: Vec<SearchResult>,
+    error: Option<String>,
+}
+
+impl SearchSecretsAns {
+    pub fn json(results: Option<Vec<SearchResult>>, err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = SearchSecretsAns {
+            results: vec![],
+            error: None,
+        };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Put results
+        if let Some(results) = results {
+            ans.results = results;
+        }
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return SearchSecretsAns::json(None, Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn search_secrets_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let args_json = match msg.body {
+        Some(body) => body,
+        None => return SearchSecretsAns::json(None, Some(Error::IncorrectReq)),
+    };
+
+    // Parse json
+    let args: SearchSecretsArgs = match serde_json::from_slice(&args_json) {
+        Ok(a) => a,
+        Err(e) => return SearchSecretsAns::json(None, Some(Error::JSON(e))),
+    };
+
+    // Lock database
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return SearchSecretsAns::json(None, Some(Error::Internal)),
+    };
+
+    match db.search_secrets(&args.query, args.limit) {
+        Ok(results) => return SearchSecretsAns::json(Some(results), None),
+        Err(err) => return SearchSecretsAns::json(None, Some(err)),
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+use key::Passwords;
+
+#[derive(Debug, Deserialize)]
+struct SetFolderExpiryArgs {
+    folder: String,
+    days: Option<u32>,
+    #[serde(default)]
+    passwords: Passwords,
+    #[serde(default)]
+    token: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SetFolderExpiryAns {
+    error: Option<String>,
+}
+
+impl SetFolderExpiryAns {
+    pub fn json(err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = SetFolderExpiryAns { error: None };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return SetFolderExpiryAns::json(Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn set_folder_expiry_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let args_json = match msg.body {
+        Some(body) => body,
+        None => return SetFolderExpiryAns::json(Some(Error::IncorrectReq)),
+    };
+
+    // Parse json
+    let args: SetFolderExpiryArgs = match serde_json::from_slice(&args_json) {
+        Ok(k) => k,
+        Err(e) => return SetFolderExpiryAns::json(Some(Error::JSON(e))),
+    };
+    let passwords = args.passwords.clone();
+    let token = args.token.clone();
+
+    // Set expiry
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return SetFolderExpiryAns::json(Some(Error::Internal)),
+    };
+    match db.with_session(token, passwords, |db, passwords| {
+        db.set_folder_expiry(&args.folder, args.days, passwords)
+    }) {
+        Ok(_) => (),
+        Err(err) => return SetFolderExpiryAns::json(Some(err)),
+    }
+
+    SetFolderExpiryAns::json(None)
+}
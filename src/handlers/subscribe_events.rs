@@ -0,0 +1,67 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+
+#[derive(Debug, Deserialize)]
+struct SubscribeEventsArgs {
+    socket_path: String,
+}
+
+#[derive(Serialize)]
+struct SubscribeEventsAns {
+    error: Option<String>,
+}
+
+impl SubscribeEventsAns {
+    pub fn json(err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = SubscribeEventsAns { error: None };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return SubscribeEventsAns::json(Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn subscribe_events_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let args_json = match msg.body {
+        Some(body) => body,
+        None => return SubscribeEventsAns::json(Some(Error::IncorrectReq)),
+    };
+
+    // Parse json
+    let args: SubscribeEventsArgs = match serde_json::from_slice(&args_json) {
+        Ok(k) => k,
+        Err(e) => return SubscribeEventsAns::json(Some(Error::JSON(e))),
+    };
+
+    // Add subscriber
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return SubscribeEventsAns::json(Some(Error::Internal)),
+    };
+    match db.subscribe(&args.socket_path) {
+        Ok(_) => (),
+        Err(err) => return SubscribeEventsAns::json(Some(err)),
+    }
+
+    SubscribeEventsAns::json(None)
+}
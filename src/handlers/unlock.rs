@@ -0,0 +1,71 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+use key::Passwords;
+
+#[derive(Debug, Clone, Deserialize)]
+struct UnlockArgs {
+    pub passwords: Passwords,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct UnlockAns {
+    token: Option<String>,
+    error: Option<String>,
+}
+
+impl UnlockAns {
+    pub fn json(token: Option<String>, err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = UnlockAns {
+            token: token,
+            error: None,
+        };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return UnlockAns::json(None, Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn unlock_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let args_json = match msg.body {
+        Some(body) => body,
+        None => return UnlockAns::json(None, Some(Error::IncorrectReq)),
+    };
+
+    // Parse json
+    let args: UnlockArgs = match serde_json::from_slice(&args_json) {
+        Ok(a) => a,
+        Err(e) => return UnlockAns::json(None, Some(Error::JSON(e))),
+    };
+
+    // Lock database
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return UnlockAns::json(None, Some(Error::Internal)),
+    };
+
+    match db.unlock(args.passwords) {
+        Ok(token) => return UnlockAns::json(Some(token), None),
+        Err(err) => return UnlockAns::json(None, Some(err)),
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+
+#[derive(Debug, Deserialize)]
+struct UnsubscribeEventsArgs {
+    socket_path: String,
+}
+
+#[derive(Serialize)]
+struct UnsubscribeEventsAns {
+    error: Option<String>,
+}
+
+impl UnsubscribeEventsAns {
+    pub fn json(err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = UnsubscribeEventsAns { error: None };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return UnsubscribeEventsAns::json(Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn unsubscribe_events_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let args_json = match msg.body {
+        Some(body) => body,
+        None => return UnsubscribeEventsAns::json(Some(Error::IncorrectReq)),
+    };
+
+    // Parse json
+    let args: UnsubscribeEventsArgs = match serde_json::from_slice(&args_json) {
+        Ok(k) => k,
+        Err(e) => return UnsubscribeEventsAns::json(Some(Error::JSON(e))),
+    };
+
+    // Remove subscriber
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return UnsubscribeEventsAns::json(Some(Error::Internal)),
+    };
+    match db.unsubscribe(&args.socket_path) {
+        Ok(_) => (),
+        Err(err) => return UnsubscribeEventsAns::json(Some(err)),
+    }
+
+    UnsubscribeEventsAns::json(None)
+}
@@ -0,0 +1,67 @@
+use std::sync::{Arc, Mutex};
+
+use con::server::SharedState;
+use con::Msg;
+use db::DB;
+use errors::Error;
+use secret::UpdateSecretArgs;
+
+#[derive(Serialize)]
+struct UpdateSecretAns {
+    error: Option<String>,
+}
+
+impl UpdateSecretAns {
+    pub fn json(err: Option<Error>) -> Option<Vec<u8>> {
+        let mut ans = UpdateSecretAns { error: None };
+
+        // Check error
+        match err {
+            Some(ref err) => {
+                ans.error = Some(err.json());
+            }
+            None => (),
+        };
+
+        // Serialize
+        let json = match serde_json::to_vec(&ans) {
+            Ok(v) => v,
+            Err(e) => return UpdateSecretAns::json(Some(Error::JSON(e))),
+        };
+
+        Some(json)
+    }
+}
+
+pub fn update_secret_handler<T>(
+    msg: Msg,
+    _: SharedState<T>,
+    shared_db: Arc<Mutex<DB>>,
+) -> Option<Vec<u8>> {
+    let args_json = match msg.body {
+        Some(body) => body,
+        None => return UpdateSecretAns::json(Some(Error::IncorrectReq)),
+    };
+
+    // Parse json
+    let args: UpdateSecretArgs = match serde_json::from_slice(&args_json) {
+        Ok(k) => k,
+        Err(e) => return UpdateSecretAns::json(Some(Error::JSON(e))),
+    };
+    let passwords = args.passwords.clone();
+    let token = args.token.clone();
+
+    // Update secret
+    let mut db = match shared_db.lock() {
+        Ok(db) => db,
+        Err(_) => return UpdateSecretAns::json(Some(Error::Internal)),
+    };
+    match db.with_session(token, passwords, |db, passwords| {
+        db.update_secret(args, passwords)
+    }) {
+        Ok(_) => (),
+        Err(err) => return UpdateSecretAns::json(Some(err)),
+    }
+
+    UpdateSecretAns::json(None)
+}
@@ -0,0 +1,344 @@
+use std::io::prelude::*;
+
+use scrypt::ScryptParams;
+
+use errors::Error;
+use utils;
+
+/// First byte of every database file
+static MAGIC: u8 = 0x00;
+
+/// Current database format version
+pub static DB_VERSION: u8 = 0x04;
+
+/// Length of outer kdf salt
+pub const SALT_LEN: usize = 16;
+
+/// Length of outer cipher nonce
+pub const NONCE_LEN: usize = 12;
+
+/// Bounds of scrypt params. Params are read before content
+/// is authenticated, so hostile file shouldn't be able to
+/// request unbounded memory (128 * r * n) or time (n * r * p).
+const MAX_LOG2_N: u8 = 24;
+const MAX_R_P: u64 = 1 << 18;
+const MAX_MEMORY: u64 = 1 << 30;
+const MAX_WORK: u64 = 1 << 26;
+
+/// Scrypt cost parameters
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct KdfParams {
+    pub log2_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl Default for KdfParams {
+    /// Default parameters for new databases
+    fn default() -> KdfParams {
+        KdfParams {
+            log2_n: 15,
+            r: 16,
+            p: 1,
+        }
+    }
+}
+
+impl KdfParams {
+    /// Parameters used by format versions 0-2
+    pub fn legacy() -> KdfParams {
+        KdfParams {
+            log2_n: 1,
+            r: 1,
+            p: 1,
+        }
+    }
+
+    /// Get scrypt params
+    pub fn scrypt(&self) -> Result<ScryptParams, Error> {
+        if !self.is_bounded() {
+            return Err(Error::IncorrectConf);
+        }
+        Ok(ScryptParams::new(self.log2_n, self.r, self.p)?)
+    }
+
+    /// Check if parameters are within bounds
+    pub fn is_bounded(&self) -> bool {
+        if self.log2_n == 0 || self.log2_n > MAX_LOG2_N || self.r == 0 || self.p == 0 {
+            return false;
+        }
+        let n = 1u64 << self.log2_n;
+        let r = self.r as u64;
+        let p = self.p as u64;
+
+        r * p <= MAX_R_P && 128 * r * n <= MAX_MEMORY && n * r * p <= MAX_WORK
+    }
+
+    /// Read parameters [log2_n, r(4), p(4)]
+    fn read<R: Read>(reader: &mut R) -> Result<KdfParams, Error> {
+        let mut bytes = [0u8; 9];
+        reader.read_exact(&mut bytes)?;
+
+        let params = KdfParams {
+            log2_n: bytes[0],
+            r: u32_from_be(&bytes[1..5]),
+            p: u32_from_be(&bytes[5..9]),
+        };
+        match params.is_bounded() {
+            true => Ok(params),
+            false => Err(Error::InvalidDBFormat),
+        }
+    }
+
+    /// Get parameters bytes
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(9);
+        bytes.push(self.log2_n);
+        bytes.extend_from_slice(&u32_to_be(self.r));
+        bytes.extend_from_slice(&u32_to_be(self.p));
+        bytes
+    }
+}
+
+/// Outer cipher of database file
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Cipher {
+    /// Legacy unauthenticated cipher (version 0)
+    Aes256Cbc,
+    Aes256Gcm,
+}
+
+impl Cipher {
+    /// Get cipher id stored in header
+    pub fn id(&self) -> u8 {
+        match self {
+            Cipher::Aes256Cbc => 0x00,
+            Cipher::Aes256Gcm => 0x01,
+        }
+    }
+
+    /// Get cipher by its id
+    pub fn from_id(id: u8) -> Result<Cipher, Error> {
+        match id {
+            0x00 => Ok(Cipher::Aes256Cbc),
+            0x01 => Ok(Cipher::Aes256Gcm),
+            _ => Err(Error::InvalidDBFormat),
+        }
+    }
+}
+
+/// Database file header
+///
+/// Layout:
+/// [MAGIC, version, version, cipher, outer_kdf(9), inner_kdf(9), salt(16), nonce(12)]
+///
+/// Version 0 files have no cipher byte and are always
+/// encrypted with AES-256-CBC. Version 0 and 1 files have
+/// no salt and nonce, those are derived from outer key.
+/// Versions 0-2 have no kdf params and use legacy ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub version: u8,
+    pub cipher: Cipher,
+    pub outer_kdf: KdfParams,
+    pub inner_kdf: KdfParams,
+    pub salt: Vec<u8>,
+    pub nonce: Vec<u8>,
+}
+
+impl Header {
+    /// Create header for current format version
+    /// with fresh random salt and nonce
+    pub fn new(outer_kdf: KdfParams, inner_kdf: KdfParams) -> Header {
+        Header {
+            version: DB_VERSION,
+            cipher: Cipher::Aes256Gcm,
+            outer_kdf: outer_kdf,
+            inner_kdf: inner_kdf,
+            salt: utils::rand_bytes(SALT_LEN),
+            nonce: utils::rand_bytes(NONCE_LEN),
+        }
+    }
+
+    /// Check if salt and nonce are stored in header
+    pub fn is_randomized(&self) -> bool {
+        self.version >= 0x02
+    }
+
+    /// Check if kdf params are stored in header
+    pub fn has_kdf_params(&self) -> bool {
+        self.version >= 0x03
+    }
+
+    /// Read and validate header
+    pub fn read<R: Read>(reader: &mut R) -> Result<Header, Error> {
+        let mut sig = [0u8; 3];
+        reader.read_exact(&mut sig)?;
+        if sig[0] != MAGIC || sig[1] != sig[2] {
+            return Err(Error::InvalidDBFormat);
+        }
+
+        let version = sig[1];
+        if version > DB_VERSION {
+            return Err(Error::InvalidDBFormat);
+        }
+
+        // Legacy format
+        if version == 0x00 {
+            return Ok(Header {
+                version: version,
+                cipher: Cipher::Aes256Cbc,
+                outer_kdf: KdfParams::legacy(),
+                inner_kdf: KdfParams::legacy(),
+                salt: vec![],
+                nonce: vec![],
+            });
+        }
+
+        let mut cipher_id = [0u8; 1];
+        reader.read_exact(&mut cipher_id)?;
+
+        let mut header = Header {
+            version: version,
+            cipher: Cipher::from_id(cipher_id[0])?,
+            outer_kdf: KdfParams::legacy(),
+            inner_kdf: KdfParams::legacy(),
+            salt: vec![],
+            nonce: vec![],
+        };
+
+        if header.has_kdf_params() {
+            header.outer_kdf = KdfParams::read(reader)?;
+            header.inner_kdf = KdfParams::read(reader)?;
+        }
+
+        if header.is_randomized() {
+            let mut salt = [0u8; SALT_LEN];
+            let mut nonce = [0u8; NONCE_LEN];
+            reader.read_exact(&mut salt)?;
+            reader.read_exact(&mut nonce)?;
+            header.salt = Vec::from(&salt[..]);
+            header.nonce = Vec::from(&nonce[..]);
+        }
+
+        Ok(header)
+    }
+
+    /// Get header bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![MAGIC, self.version, self.version];
+        if self.version > 0x00 {
+            bytes.push(self.cipher.id());
+        }
+        if self.has_kdf_params() {
+            bytes.append(&mut self.outer_kdf.to_bytes());
+            bytes.append(&mut self.inner_kdf.to_bytes());
+        }
+        if self.is_randomized() {
+            bytes.extend_from_slice(&self.salt);
+            bytes.extend_from_slice(&self.nonce);
+        }
+        bytes
+    }
+}
+
+/// Encode u32 as big-endian bytes
+fn u32_to_be(v: u32) -> [u8; 4] {
+    [(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8]
+}
+
+/// Decode u32 from big-endian bytes
+fn u32_from_be(b: &[u8]) -> u32 {
+    ((b[0] as u32) << 24) | ((b[1] as u32) << 16) | ((b[2] as u32) << 8) | b[3] as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use errors::Error;
+    use header::*;
+
+    #[test]
+    fn header_roundtrip() {
+        let outer_kdf = KdfParams {
+            log2_n: 14,
+            r: 8,
+            p: 1,
+        };
+        let inner_kdf = KdfParams {
+            log2_n: 1,
+            r: 70000,
+            p: 2,
+        };
+        let header = Header::new(outer_kdf, inner_kdf);
+        let bytes = header.to_bytes();
+        let parsed = Header::read(&mut &bytes[..]).unwrap();
+
+        assert_eq!(parsed, header);
+        assert_eq!(parsed.cipher, Cipher::Aes256Gcm);
+        assert_eq!(parsed.salt.len(), SALT_LEN);
+        assert_eq!(parsed.nonce.len(), NONCE_LEN);
+        assert_eq!(parsed.outer_kdf, outer_kdf);
+        assert_eq!(parsed.inner_kdf, inner_kdf);
+    }
+
+    #[test]
+    fn fresh_salt_and_nonce() {
+        let header_1 = Header::new(KdfParams::legacy(), KdfParams::legacy());
+        let header_2 = Header::new(KdfParams::legacy(), KdfParams::legacy());
+
+        assert!(header_1.salt != header_2.salt);
+        assert!(header_1.nonce != header_2.nonce);
+    }
+
+    #[test]
+    fn legacy_header() {
+        let bytes = [0x00u8, 0x00, 0x00, 0xff];
+        let parsed = Header::read(&mut &bytes[..]).unwrap();
+
+        assert_eq!(parsed.version, 0x00);
+        assert_eq!(parsed.cipher, Cipher::Aes256Cbc);
+        assert_eq!(parsed.outer_kdf, KdfParams::legacy());
+    }
+
+    #[test]
+    fn invalid_header() {
+        let bytes = [0x00u8, 0x01, 0x02, 0x01];
+        assert!(Header::read(&mut &bytes[..]).is_err());
+
+        let bytes = [0x00u8, 0x01, 0x01, 0x7f];
+        assert!(Header::read(&mut &bytes[..]).is_err());
+
+        // Truncated salt
+        let bytes = [0x00u8, 0x02, 0x02, 0x01, 0x01, 0x02];
+        assert!(Header::read(&mut &bytes[..]).is_err());
+    }
+
+    #[test]
+    fn bounded_kdf_params() {
+        assert!(KdfParams::default().is_bounded());
+        assert!(KdfParams::legacy().is_bounded());
+
+        let kdf = |log2_n, r, p| KdfParams {
+            log2_n: log2_n,
+            r: r,
+            p: p,
+        };
+        let huge = [
+            kdf(40, 1, 1),
+            kdf(20, 16, 1),
+            kdf(1, 1 << 20, 1 << 10),
+            kdf(15, 16, 1000),
+            kdf(15, 0, 1),
+        ];
+        for kdf in huge.iter() {
+            assert!(!kdf.is_bounded());
+            assert!(kdf.scrypt().is_err());
+
+            let header = Header::new(*kdf, KdfParams::legacy());
+            match Header::read(&mut &header.to_bytes()[..]) {
+                Err(Error::InvalidDBFormat) => (),
+                other => panic!("Expected format error, got {:?}", other),
+            }
+        }
+    }
+}
@@ -2,11 +2,34 @@ use std::collections::HashMap;
 use std::fs;
 use sha2::{Sha256, Digest};
 use chrono::Local;
+use block_cipher_trait::generic_array::typenum::consts::U16;
+use block_cipher_trait::generic_array::GenericArray;
+use block_modes::BlockMode;
 
-use utils;
+use utils::{self, Aes256Cbc};
 use errors::Error;
+use secure::{SecretBuf, SecretString};
 
-pub type Passwords = HashMap<String, String>;
+pub type Passwords = HashMap<String, SecretString>;
+
+/// Derived key of keys group.
+/// Used for wrapping data keys of secrets.
+#[derive(Clone)]
+pub struct GroupKey {
+    key: SecretBuf,
+    iv: GenericArray<u8, U16>,
+}
+
+impl GroupKey {
+    pub fn new(key: SecretBuf, iv: GenericArray<u8, U16>) -> GroupKey {
+        GroupKey { key: key, iv: iv }
+    }
+
+    /// Get fresh cipher
+    pub fn cipher(&self) -> Result<Aes256Cbc, Error> {
+        Ok(Aes256Cbc::new_var(&self.key, &self.iv)?)
+    }
+}
 
 /// Key types
 /// 
@@ -26,8 +49,11 @@ pub struct AddKeyArgs {
     pub key_type: KeyType,
     pub name: String,
     pub group: Option<String>,
-    pub value: String,
+    pub value: SecretString,
+    #[serde(default)]
     pub passwords: Passwords,
+    #[serde(default)]
+    pub token: Option<String>,
 }
 
 /// Key info
@@ -61,11 +87,11 @@ impl Key {
         let hs = match args.key_type {
             KeyType::Text => {
                 let mut hasher = Sha256::new();
-                hasher.input(args.value.clone());
+                hasher.input(args.value.as_bytes());
                 Vec::from(hasher.result().as_slice())
             },
             KeyType::File => {
-                let file_data = fs::read(args.value.clone())?;
+                let file_data = fs::read(args.value.as_str())?;
                 let mut hasher_1 = Sha256::new();
                 hasher_1.input(file_data);
                 let mut hasher_2 = Sha256::new();
@@ -76,7 +102,7 @@ impl Key {
 
         // Get file-key addr
         let addr = match args.key_type {
-            KeyType::File => Some(args.value),
+            KeyType::File => Some(args.value.as_str().to_string()),
             _ => None,
         };
 
@@ -112,7 +138,7 @@ impl Key {
                 };
 
                 let mut hasher = Sha256::new();
-                hasher.input(kval);
+                hasher.input(kval.as_bytes());
                 let test_hs_arr = hasher.result();
                 let test_hs = test_hs_arr.as_slice();
 
@@ -163,6 +189,7 @@ impl<'a> From<&'a Key> for KeyInfo {
 mod tests {
     use std::collections::HashMap;
     use key::*;
+    use secure::SecretString;
 
     #[test]
     fn creating_new_key_from_args() {
@@ -171,8 +198,9 @@ mod tests {
             key_type: KeyType::Text,
             name: "JustKey".to_string(),
             group: None,
-            value: "Passwordf".to_string(),
+            value: SecretString::new("Passwordf".to_string()),
             passwords: HashMap::new(),
+            token: None,
         };
 
         // create key
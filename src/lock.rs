@@ -0,0 +1,166 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, prelude::*, ErrorKind, SeekFrom};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use fs2::{self, FileExt};
+
+use errors::Error;
+
+/// Advisory lock of database between processes
+///
+/// Database file is replaced on every save, so the lock
+/// is taken on separate file next to it. Exclusive owner
+/// writes its pid there to be named by others, so the file
+/// is readable by everyone.
+#[derive(Debug)]
+pub struct DbLock {
+    file: File,
+    shared: bool,
+    writable: bool,
+}
+
+impl DbLock {
+    /// Lock database, shared lock is for read-only access
+    pub fn acquire(db_path: &Path, shared: bool) -> Result<DbLock, Error> {
+        let path = lock_path(db_path);
+        if let Some(parent) = path.parent() {
+            if !parent.exists() {
+                fs::create_dir_all(&parent)?;
+            }
+        }
+        let opened = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .mode(0o644)
+            .open(&path);
+        let (mut file, writable) = match opened {
+            Ok(file) => {
+                // Lock files of older versions were private
+                let _ = file.set_permissions(fs::Permissions::from_mode(0o644));
+                (file, true)
+            }
+            // Lock file of other user can still be locked
+            Err(ref e) if e.kind() == ErrorKind::PermissionDenied => {
+                (File::open(&path)?, false)
+            }
+            Err(e) => return Err(Error::IO(e)),
+        };
+
+        let locked = match shared {
+            true => file.try_lock_shared(),
+            false => file.try_lock_exclusive(),
+        };
+        match locked {
+            Ok(_) => (),
+            Err(ref e) if is_contended(e) => return Err(holder_error(&mut file, shared)?),
+            Err(e) => return Err(Error::IO(e)),
+        }
+
+        if !shared && writable {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            write!(file, "{}", process::id())?;
+            file.sync_all()?;
+        }
+
+        Ok(DbLock {
+            file: file,
+            shared: shared,
+            writable: writable,
+        })
+    }
+}
+
+impl Drop for DbLock {
+    fn drop(&mut self) {
+        // Lock itself is released with file
+        if !self.shared && self.writable {
+            let _ = self.file.set_len(0);
+        }
+    }
+}
+
+/// Check if lock failed because of other holder
+fn is_contended(error: &io::Error) -> bool {
+    error.raw_os_error() == fs2::lock_contended_error().raw_os_error()
+}
+
+/// Get error describing current holder of contended lock
+fn holder_error(file: &mut File, shared: bool) -> Result<Error, Error> {
+    // Shared lock only conflicts with exclusive one, for
+    // exclusive lock probe if holders are read-only
+    if !shared {
+        match file.try_lock_shared() {
+            Ok(_) => {
+                file.unlock()?;
+                return Ok(Error::LockedShared);
+            }
+            Err(ref e) if is_contended(e) => (),
+            Err(e) => return Err(Error::IO(e)),
+        }
+    }
+
+    // Exclusive holder, pid is unknown if not written yet
+    // or holder could not write the file
+    let mut pid = String::new();
+    file.read_to_string(&mut pid)?;
+    Ok(Error::Locked(pid.trim().parse().ok()))
+}
+
+/// Get path of lock file of database
+fn lock_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(".lock");
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use errors::Error;
+    use lock::*;
+    use utils;
+
+    #[test]
+    fn locking() {
+        let path = env::temp_dir().join(format!("pasd_lock_{}", utils::uid()));
+
+        // Exclusive
+        let lock = DbLock::acquire(&path, false).unwrap();
+        match DbLock::acquire(&path, false) {
+            Err(Error::Locked(Some(pid))) => assert_eq!(pid, process::id()),
+            other => panic!("Expected lock error, got {:?}", other),
+        }
+        assert!(DbLock::acquire(&path, true).is_err());
+
+        // Readable by others, exclusive holder without pid
+        let lock_file = File::open(lock_path(&path)).unwrap();
+        let mode = lock_file.metadata().unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o644);
+        OpenOptions::new().write(true).open(lock_path(&path)).unwrap().set_len(0).unwrap();
+        match DbLock::acquire(&path, false) {
+            Err(Error::Locked(None)) => (),
+            other => panic!("Expected lock error, got {:?}", other),
+        }
+        drop(lock);
+
+        // Shared
+        let lock_1 = DbLock::acquire(&path, true).unwrap();
+        let lock_2 = DbLock::acquire(&path, true).unwrap();
+        match DbLock::acquire(&path, false) {
+            Err(Error::LockedShared) => (),
+            other => panic!("Expected lock error, got {:?}", other),
+        }
+        drop(lock_1);
+        drop(lock_2);
+        assert!(DbLock::acquire(&path, false).is_ok());
+
+        fs::remove_file(lock_path(&path)).unwrap();
+    }
+}
@@ -0,0 +1,170 @@
+use dbus::{self, BusType, Connection, ConnectionItem, Message};
+
+/// Login manager of systemd
+const LOGIND_NAME: &str = "org.freedesktop.login1";
+const MANAGER_IFACE: &str = "org.freedesktop.login1.Manager";
+const SESSION_IFACE: &str = "org.freedesktop.login1.Session";
+
+/// Timeout of waiting for signals (ms)
+const WAIT_TIMEOUT_MS: i32 = 1000;
+
+/// Event of login manager after which database should be locked
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LockEvent {
+    /// System is going to suspend or hibernate
+    Sleep,
+    /// Screen of session is locked
+    Lock,
+}
+
+/// Connect to bus of logind. Bus address may be set to
+/// use stand-in bus instead of system one.
+pub fn connect(address: Option<&str>) -> Result<Connection, dbus::Error> {
+    let conn = match address {
+        Some(address) => {
+            let conn = Connection::open_private(address)?;
+            conn.register()?;
+            conn
+        }
+        None => Connection::get_private(BusType::System)?,
+    };
+
+    // Lock signals of all sessions, since daemon may serve
+    // clients of any of them. Only logind itself is trusted.
+    conn.add_match(&format!(
+        "type='signal',sender='{}',interface='{}',member='PrepareForSleep'",
+        LOGIND_NAME, MANAGER_IFACE
+    ))?;
+    conn.add_match(&format!(
+        "type='signal',sender='{}',interface='{}',member='Lock'",
+        LOGIND_NAME, SESSION_IFACE
+    ))?;
+
+    Ok(conn)
+}
+
+/// Wait for lock events and handle them (blocks forever)
+pub fn watch<F: FnMut(LockEvent)>(conn: &Connection, mut on_event: F) {
+    for item in conn.iter(WAIT_TIMEOUT_MS) {
+        if let ConnectionItem::Signal(ref msg) = item {
+            if let Some(event) = lock_event(msg) {
+                on_event(event);
+            }
+        }
+    }
+}
+
+/// Get lock event of signal
+pub fn lock_event(msg: &Message) -> Option<LockEvent> {
+    let iface = msg.interface()?;
+    let member = msg.member()?;
+
+    match (&*iface, &*member) {
+        // Sent with 'false' after resume
+        (MANAGER_IFACE, "PrepareForSleep") => match msg.get1::<bool>() {
+            Some(true) => Some(LockEvent::Sleep),
+            _ => None,
+        },
+        (SESSION_IFACE, "Lock") => Some(LockEvent::Lock),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use dbus::{Connection, Message};
+
+    use logind::*;
+
+    /// Private bus daemon, stopped on drop
+    struct PrivateBus(Child);
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    /// Start private bus and get its address
+    /// (None if dbus-daemon isn't installed)
+    fn private_bus() -> Option<(PrivateBus, String)> {
+        let mut child = match Command::new("dbus-daemon")
+            .args(&["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(_) => return None,
+        };
+        let mut address = String::new();
+        if let Some(ref mut stdout) = child.stdout {
+            BufReader::new(stdout).read_line(&mut address).unwrap();
+        }
+        Some((PrivateBus(child), address.trim().to_string()))
+    }
+
+    fn sleep_signal(start: bool) -> Message {
+        Message::new_signal("/org/freedesktop/login1", MANAGER_IFACE, "PrepareForSleep")
+            .unwrap()
+            .append1(start)
+    }
+
+    fn lock_signal() -> Message {
+        Message::new_signal("/org/freedesktop/login1/session/_31", SESSION_IFACE, "Lock").unwrap()
+    }
+
+    #[test]
+    fn lock_events() {
+        assert_eq!(lock_event(&sleep_signal(true)), Some(LockEvent::Sleep));
+        assert_eq!(lock_event(&sleep_signal(false)), None);
+        assert_eq!(lock_event(&lock_signal()), Some(LockEvent::Lock));
+
+        let unlock = Message::new_signal("/", SESSION_IFACE, "Unlock").unwrap();
+        assert_eq!(lock_event(&unlock), None);
+    }
+
+    /// Private bus stands in for system bus of logind
+    #[test]
+    fn stand_in_bus() {
+        // Skipped without dbus-daemon
+        let (_bus, address) = match private_bus() {
+            Some(bus) => bus,
+            None => return,
+        };
+
+        // Connection can't be shared between threads
+        let (tx, rx) = mpsc::channel();
+        let watch_address = address.clone();
+        thread::spawn(move || {
+            let conn = connect(Some(&watch_address)).unwrap();
+            tx.send(None).unwrap();
+            watch(&conn, |event| tx.send(Some(event)).unwrap());
+        });
+        let timeout = Duration::from_secs(5);
+        assert_eq!(rx.recv_timeout(timeout).unwrap(), None);
+
+        // Signals of other senders are ignored
+        let other = Connection::open_private(&address).unwrap();
+        other.register().unwrap();
+        other.send(lock_signal()).unwrap();
+        other.flush();
+
+        let sender = Connection::open_private(&address).unwrap();
+        sender.register().unwrap();
+        sender.register_name(LOGIND_NAME, 0).unwrap();
+        sender.send(sleep_signal(false)).unwrap();
+        sender.send(sleep_signal(true)).unwrap();
+        sender.send(lock_signal()).unwrap();
+        sender.flush();
+
+        assert_eq!(rx.recv_timeout(timeout).unwrap(), Some(LockEvent::Sleep));
+        assert_eq!(rx.recv_timeout(timeout).unwrap(), Some(LockEvent::Lock));
+    }
+}
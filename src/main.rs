@@ -1,6 +1,8 @@
 #![forbid(unsafe_code)]
 extern crate chrono;
 extern crate con;
+#[cfg(target_os = "linux")]
+extern crate dbus;
 extern crate rand;
 extern crate rmp_serde as msgpack;
 extern crate serde;
@@ -8,19 +10,47 @@ extern crate serde_json;
 #[macro_use]
 extern crate serde_derive;
 extern crate aes;
+extern crate aes_gcm;
 extern crate block_cipher_trait;
 extern crate block_modes;
+extern crate fs2;
 extern crate hmac;
+extern crate regex;
+extern crate region;
+extern crate rlimit;
 extern crate scrypt;
+extern crate sha1;
 extern crate sha2;
+extern crate signal_hook;
 extern crate toml;
+extern crate url;
+extern crate zeroize;
 
+pub mod audit;
+pub mod backup;
+pub mod breach;
+pub mod cache;
 pub mod config;
 pub mod db;
 pub mod errors;
+pub mod expiry;
 pub mod handlers;
+pub mod header;
 pub mod key;
+pub mod lock;
+#[cfg(target_os = "linux")]
+pub mod logind;
+pub mod merge;
+pub mod migrations;
+pub mod notify;
+pub mod otp;
+pub mod password;
+pub mod query;
+pub mod search;
 pub mod secret;
+pub mod secure;
+pub mod session;
+pub mod url_match;
 pub mod utils;
 
 use config::Config;
@@ -28,24 +58,158 @@ use db::DB;
 use errors::Error;
 use handlers::add_key::add_key_handler;
 use handlers::add_secret::add_secret_handler;
+use handlers::audit::audit_handler;
+use handlers::generate_password::generate_password_handler;
+use handlers::get_otp::get_otp_handler;
 use handlers::get_secret::get_secret_handler;
+use handlers::get_secret_history::get_secret_history_handler;
+use handlers::list_backups::list_backups_handler;
+use handlers::list_expiring::list_expiring_handler;
 use handlers::list_keys::list_key_handler;
 use handlers::list_secrets::list_secrets_handler;
+use handlers::lock::lock_handler;
+use handlers::lock_all::lock_all_handler;
+use handlers::match_url::match_url_handler;
+use handlers::move_folder::move_folder_handler;
+use handlers::rekey_kdf::rekey_kdf_handler;
 use handlers::remove_key::remove_key_handler;
 use handlers::remove_secret::remove_secret_handler;
+use handlers::rename_tag::rename_tag_handler;
+use handlers::restore_backup::restore_backup_handler;
+use handlers::restore_secret_history::restore_secret_history_handler;
+use handlers::search_secrets::search_secrets_handler;
+use handlers::set_folder_expiry::set_folder_expiry_handler;
+use handlers::subscribe_events::subscribe_events_handler;
+use handlers::unlock::unlock_handler;
+use handlers::unsubscribe_events::unsubscribe_events_handler;
+use handlers::update_secret::update_secret_handler;
+use signal_hook::iterator::Signals;
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
 fn main() -> Result<(), Error> {
+    // Keep secrets out of core dumps
+    if let Err(err) = secure::disable_core_dumps() {
+        println!("Cannot disable core dumps: {:?}", err);
+    }
+
     // Initialize config and database
     let config = Config::load();
-    let db = DB::new(&config);
+    let mut db = DB::new(&config);
+
+    // Fail fast if database is used by other daemon
+    if config.db_path.is_some() {
+        if let Err(err) = db.acquire_lock() {
+            println!("Cannot lock database: {}", err);
+            return Err(err);
+        }
+    }
 
     // ---
     let shared_db = Arc::new(Mutex::new(db));
 
+    // Check expiry of secrets in background
+    let expiry_db = shared_db.clone();
+    let expiry_interval = config.expiry_check_interval.unwrap_or(3600).max(1);
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(expiry_interval));
+        let mut db = match expiry_db.lock() {
+            Ok(db) => db,
+            Err(_) => return,
+        };
+        if let Err(err) = db.check_expiry() {
+            println!("Cannot check expiry: {:?}", err);
+        }
+    });
+
+    // Deliver events to subscribers outside of database lock
+    let notify_db = shared_db.clone();
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(1));
+        let outbox = match notify_db.lock() {
+            Ok(mut db) => db.notifier.take_outbox(),
+            Err(_) => return,
+        };
+        if let Some(outbox) = outbox {
+            let failed = outbox.deliver();
+            match notify_db.lock() {
+                Ok(mut db) => db.notifier.remove(&failed),
+                Err(_) => return,
+            }
+        }
+    });
+
+    // Lock sessions when they aren't used
+    let idle_db = shared_db.clone();
+    let auto_lock_idle = config.auto_lock_idle.unwrap_or(900);
+    if auto_lock_idle > 0 {
+        let idle = Duration::from_secs(auto_lock_idle);
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(auto_lock_idle.min(10)));
+            match idle_db.lock() {
+                Ok(mut db) => db.lock_if_idle(idle),
+                Err(_) => return,
+            };
+        });
+    }
+
+    // Lock sessions on SIGUSR1
+    let signal_db = shared_db.clone();
+    let signals = Signals::new(&[signal_hook::SIGUSR1])?;
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            match signal_db.lock() {
+                Ok(mut db) => db.lock_all(),
+                Err(_) => return,
+            }
+        }
+    });
+
+    // Lock sessions on suspend and screen lock
+    #[cfg(target_os = "linux")]
+    {
+        if config.lock_on_sleep.unwrap_or(true) {
+            let logind_db = shared_db.clone();
+            let address = config.logind_bus_address.clone();
+            thread::spawn(move || {
+                let conn = match logind::connect(address.as_ref().map(String::as_str)) {
+                    Ok(c) => c,
+                    Err(err) => {
+                        println!("Cannot connect to logind: {:?}", err);
+                        return;
+                    }
+                };
+                logind::watch(&conn, |_| {
+                    if let Ok(mut db) = logind_db.lock() {
+                        db.lock_all();
+                    }
+                });
+            });
+        }
+    }
+
     // Setup server
     let mut server = con::Server::new(shared_db);
 
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("unlock"),
+        unlock_handler,
+    )?;
+
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("lock"),
+        lock_handler,
+    )?;
+
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("lock-all"),
+        lock_all_handler,
+    )?;
+
     server.on(
         con::ClientName::Any,
         con::MsgName::Is("add-key"),
@@ -58,6 +222,12 @@ fn main() -> Result<(), Error> {
         add_secret_handler,
     )?;
 
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("update-secret"),
+        update_secret_handler,
+    )?;
+
     server.on(
         con::ClientName::Any,
         con::MsgName::Is("remove-key"),
@@ -82,12 +252,108 @@ fn main() -> Result<(), Error> {
         list_secrets_handler,
     )?;
 
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("search-secrets"),
+        search_secrets_handler,
+    )?;
+
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("match-url"),
+        match_url_handler,
+    )?;
+
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("list-expiring"),
+        list_expiring_handler,
+    )?;
+
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("set-folder-expiry"),
+        set_folder_expiry_handler,
+    )?;
+
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("subscribe-events"),
+        subscribe_events_handler,
+    )?;
+
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("unsubscribe-events"),
+        unsubscribe_events_handler,
+    )?;
+
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("rename-tag"),
+        rename_tag_handler,
+    )?;
+
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("move-folder"),
+        move_folder_handler,
+    )?;
+
     server.on(
         con::ClientName::Any,
         con::MsgName::Is("get-secret"),
         get_secret_handler,
     )?;
 
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("get-otp"),
+        get_otp_handler,
+    )?;
+
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("get-secret-history"),
+        get_secret_history_handler,
+    )?;
+
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("restore-secret-history"),
+        restore_secret_history_handler,
+    )?;
+
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("audit"),
+        audit_handler,
+    )?;
+
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("generate-password"),
+        generate_password_handler,
+    )?;
+
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("rekey-kdf"),
+        rekey_kdf_handler,
+    )?;
+
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("list-backups"),
+        list_backups_handler,
+    )?;
+
+    server.on(
+        con::ClientName::Any,
+        con::MsgName::Is("restore-backup"),
+        restore_backup_handler,
+    )?;
+
     // Listen clients (blocked)
     let sock_path = config.ipc_socket_path.unwrap_or("/tmp/pasd.sock".to_string());
     match server.listen(&sock_path) {
@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use header::KdfParams;
+use key::Key;
+use msgpack;
+use secret::Secret;
+
+/// Changes that can't be merged automatically
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conflict {
+    /// Keys (or kdf) changed by one side along with
+    /// secrets changed by other
+    Keys,
+    /// Id of item changed differently by both sides
+    Item(String),
+}
+
+/// Content of database to merge
+#[derive(Debug)]
+pub struct State {
+    pub outer_kdf: KdfParams,
+    pub inner_kdf: KdfParams,
+    pub keys: Vec<Key>,
+    pub secrets: Vec<Secret>,
+    pub folder_expiry: HashMap<String, u32>,
+    pub used: HashMap<String, i64>,
+}
+
+/// Three-way merge of database by ids of keys and secrets
+pub fn merge_states(base: &State, ours: &State, theirs: &State) -> Result<State, Conflict> {
+    // Data keys of secrets are wrapped for key groups, so
+    // secrets can't be merged with keys of other side
+    let rekeyed = |s: &State| -> bool {
+        !same(&base.keys, &s.keys) || base.inner_kdf != s.inner_kdf || base.outer_kdf != s.outer_kdf
+    };
+    let ours_rekeyed = rekeyed(ours);
+    let theirs_rekeyed = rekeyed(theirs);
+    let ours_changed = !same(&base.secrets, &ours.secrets);
+    let theirs_changed = !same(&base.secrets, &theirs.secrets);
+    if (ours_rekeyed && (theirs_rekeyed || theirs_changed)) || (theirs_rekeyed && ours_changed) {
+        return Err(Conflict::Keys);
+    }
+
+    let expiry = |s: &State| -> Vec<(String, u32)> {
+        s.folder_expiry.iter().map(|(f, d)| (f.clone(), *d)).collect()
+    };
+    let folder_expiry = merge(&expiry(base), &expiry(ours), &expiry(theirs), |e| &e.0)?;
+
+    let mut used = ours.used.clone();
+    merge_used(&mut used, &theirs.used);
+
+    let kdf_side = match theirs_rekeyed {
+        true => theirs,
+        false => ours,
+    };
+
+    Ok(State {
+        outer_kdf: kdf_side.outer_kdf,
+        inner_kdf: kdf_side.inner_kdf,
+        keys: merge(&base.keys, &ours.keys, &theirs.keys, |k| &k.id)?,
+        secrets: merge(&base.secrets, &ours.secrets, &theirs.secrets, |s| &s.id)?,
+        folder_expiry: folder_expiry.into_iter().collect(),
+        used: used,
+    })
+}
+
+/// Merge times of use of secrets, the latest one is kept
+pub fn merge_used(used: &mut HashMap<String, i64>, other: &HashMap<String, i64>) {
+    for (id, date) in other.iter() {
+        let latest = used.entry(id.clone()).or_insert(*date);
+        if *latest < *date {
+            *latest = *date;
+        }
+    }
+}
+
+/// Three-way merge of items by id. Items changed (added,
+/// updated or removed) only by one side since base keep
+/// that change. Order of theirs is kept, items added by
+/// ours are appended.
+pub fn merge<T, F>(base: &[T], ours: &[T], theirs: &[T], id: F) -> Result<Vec<T>, Conflict>
+where
+    T: Clone + Serialize,
+    F: Fn(&T) -> &str,
+{
+    let base_items = index(base, &id);
+    let our_items = index(ours, &id);
+    let their_items = index(theirs, &id);
+    let mut merged = Vec::with_capacity(theirs.len().max(ours.len()));
+
+    for their in theirs.iter() {
+        let item_id = id(their);
+        let their = &their_items[item_id];
+        let item = match (base_items.get(item_id), our_items.get(item_id)) {
+            // Added by both
+            (None, Some(our)) => match our.same(their) {
+                true => Some(our.item),
+                false => return Err(Conflict::Item(item_id.to_string())),
+            },
+            // Added by them
+            (None, None) => Some(their.item),
+            // Changed by them or both
+            (Some(b), Some(our)) => match (b.same(our), b.same(their)) {
+                (true, _) => Some(their.item),
+                (false, true) => Some(our.item),
+                (false, false) if our.same(their) => Some(our.item),
+                _ => return Err(Conflict::Item(item_id.to_string())),
+            },
+            // Removed by us
+            (Some(b), None) => match b.same(their) {
+                true => None,
+                false => return Err(Conflict::Item(item_id.to_string())),
+            },
+        };
+        if let Some(item) = item {
+            merged.push(item.clone());
+        }
+    }
+
+    for our in ours.iter() {
+        let item_id = id(our);
+        if their_items.contains_key(item_id) {
+            continue;
+        }
+        match base_items.get(item_id) {
+            // Added by us
+            None => merged.push(our.clone()),
+            // Removed by them
+            Some(b) => {
+                if !b.same(&our_items[item_id]) {
+                    return Err(Conflict::Item(item_id.to_string()));
+                }
+            }
+        }
+    }
+
+    Ok(merged)
+}
+
+/// Item with its serialized form
+struct Indexed<'a, T: 'a> {
+    item: &'a T,
+    form: Option<Vec<u8>>,
+}
+
+impl<'a, T: 'a> Indexed<'a, T> {
+    /// Check if items are equal by their serialized form
+    fn same(&self, other: &Indexed<T>) -> bool {
+        match (&self.form, &other.form) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Index items by id, each one is serialized once
+fn index<'a, T, F>(items: &'a [T], id: &F) -> HashMap<&'a str, Indexed<'a, T>>
+where
+    T: Serialize,
+    F: Fn(&T) -> &str,
+{
+    items
+        .iter()
+        .map(|item| {
+            let indexed = Indexed {
+                item: item,
+                form: msgpack::to_vec(item).ok(),
+            };
+            (id(item), indexed)
+        })
+        .collect()
+}
+
+/// Check if items are equal by their serialized form
+pub fn same<T: Serialize>(a: &T, b: &T) -> bool {
+    match (msgpack::to_vec(a), msgpack::to_vec(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use merge::*;
+
+    fn items(list: &[(&str, u32)]) -> Vec<(String, u32)> {
+        list.iter().map(|(id, v)| (id.to_string(), *v)).collect()
+    }
+
+    fn merge_items(
+        base: &[(&str, u32)],
+        ours: &[(&str, u32)],
+        theirs: &[(&str, u32)],
+    ) -> Result<Vec<(String, u32)>, Conflict> {
+        merge(&items(base), &items(ours), &items(theirs), |i| &i.0)
+    }
+
+    #[test]
+    fn three_way() {
+        let base = [("a", 1), ("b", 1), ("c", 1), ("d", 1)];
+
+        // Changes of different items
+        let merged = merge_items(
+            &base,
+            &[("a", 2), ("b", 1), ("d", 1), ("e", 1)],
+            &[("a", 1), ("b", 3), ("c", 1), ("f", 1)],
+        );
+        assert_eq!(
+            merged.unwrap(),
+            items(&[("a", 2), ("b", 3), ("f", 1), ("e", 1)])
+        );
+
+        // Same change by both
+        let merged = merge_items(&base, &[("a", 2)], &[("a", 2)]);
+        assert_eq!(merged.unwrap(), items(&[("a", 2)]));
+
+        // Different changes of the same item
+        let conflict = merge_items(&base, &[("a", 2)], &[("a", 3)]).unwrap_err();
+        assert_eq!(conflict, Conflict::Item("a".to_string()));
+
+        // Changed by one side and removed by other
+        assert!(merge_items(&base, &[("b", 2)], &[]).is_err());
+        assert!(merge_items(&base, &[], &[("c", 2)]).is_err());
+    }
+
+    fn state(kdf: KdfParams) -> State {
+        State {
+            outer_kdf: kdf,
+            inner_kdf: kdf,
+            keys: vec![],
+            secrets: vec![],
+            folder_expiry: HashMap::new(),
+            used: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn rekeyed_states() {
+        let base = state(KdfParams::default());
+
+        // Both kdf params of rekeyed side are kept
+        let mut ours = state(KdfParams::default());
+        ours.folder_expiry.insert("work".to_string(), 90);
+        let merged = merge_states(&base, &ours, &state(KdfParams::legacy())).unwrap();
+        assert_eq!(merged.outer_kdf, KdfParams::legacy());
+        assert_eq!(merged.inner_kdf, KdfParams::legacy());
+        assert_eq!(merged.folder_expiry.get("work"), Some(&90));
+
+        // Latest use of secret is kept
+        let mut ours = state(KdfParams::default());
+        ours.used.insert("a".to_string(), 10);
+        ours.used.insert("b".to_string(), 30);
+        let mut theirs = state(KdfParams::default());
+        theirs.used.insert("a".to_string(), 20);
+        theirs.used.insert("b".to_string(), 5);
+        let merged = merge_states(&base, &ours, &theirs).unwrap();
+        assert_eq!(merged.used.get("a"), Some(&20));
+        assert_eq!(merged.used.get("b"), Some(&30));
+
+        // Rekeyed by both
+        let conflict = merge_states(
+            &base,
+            &state(KdfParams::legacy()),
+            &state(KdfParams::legacy()),
+        ).unwrap_err();
+        assert_eq!(conflict, Conflict::Keys);
+    }
+}
@@ -0,0 +1,63 @@
+use errors::Error;
+use header::DB_VERSION;
+use secure::SecretBuf;
+
+/// Upgrade decrypted database payload from one
+/// format version to the next one
+pub type Migration = fn(SecretBuf) -> Result<SecretBuf, Error>;
+
+/// Registered migrations by source version
+static MIGRATIONS: &[(u8, Migration)] = &[
+    // 0 -> 1: authenticated outer cipher
+    (0x00, keep_payload as Migration),
+    // 1 -> 2: random outer salt and nonce
+    (0x01, keep_payload as Migration),
+    // 2 -> 3: kdf params in header
+    (0x02, keep_payload as Migration),
+    // 3 -> 4: per-secret data keys, legacy secrets
+    // get their own data key on the next key change
+    (0x03, keep_payload as Migration),
+];
+
+/// Run migrations from provided version up to the current one
+pub fn migrate(version: u8, data: SecretBuf) -> Result<SecretBuf, Error> {
+    let mut version = version;
+    let mut data = data;
+
+    while version < DB_VERSION {
+        let migration = match MIGRATIONS.iter().find(|(v, _)| *v == version) {
+            Some((_, m)) => m,
+            None => return Err(Error::InvalidDBFormat),
+        };
+        data = migration(data)?;
+        version += 1;
+    }
+
+    Ok(data)
+}
+
+/// Migration for format changes that touch only
+/// header or outer encryption
+fn keep_payload(data: SecretBuf) -> Result<SecretBuf, Error> {
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use header::DB_VERSION;
+    use migrations::*;
+    use secure::SecretBuf;
+
+    #[test]
+    fn all_versions_registered() {
+        for version in 0..DB_VERSION {
+            assert!(MIGRATIONS.iter().any(|(v, _)| *v == version));
+        }
+    }
+
+    #[test]
+    fn migrate_from_current() {
+        let data = SecretBuf::new(vec![1, 2, 3]);
+        assert_eq!(migrate(DB_VERSION, data.clone()).unwrap(), data);
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, prelude::*};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use errors::Error;
+use expiry::ExpiringSecret;
+use secret::SecretInfo;
+use utils;
+
+/// Max time of connecting and writing event to subscriber
+const WRITE_TIMEOUT_MS: u64 = 1000;
+
+/// Event sent to subscribed clients
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    SecretExpired { secret: SecretInfo, expires: i64 },
+    /// External changes of database were saved to copy
+    MergeConflict { copy: String, item: Option<String> },
+}
+
+/// Subscribed clients and already sent notifications
+///
+/// Clients listen on their own unix sockets, every event
+/// is written there as a line of json. Events are queued
+/// and delivered outside of database lock (see `Outbox`).
+/// Subscribers that aren't listening anymore are removed.
+#[derive(Debug, Default)]
+pub struct Notifier {
+    subscribers: Vec<PathBuf>,
+    /// Secret id -> expiry time already notified
+    notified: HashMap<String, i64>,
+    /// Events waiting for delivery
+    pending: Vec<Event>,
+}
+
+/// Events taken from notifier for delivery
+#[derive(Debug)]
+pub struct Outbox {
+    subscribers: Vec<PathBuf>,
+    events: Vec<Event>,
+}
+
+impl Notifier {
+    pub fn subscribe(&mut self, path: PathBuf) {
+        if !self.subscribers.contains(&path) {
+            self.subscribers.push(path);
+        }
+    }
+
+    /// Remove subscriber, return false if there was no such one
+    pub fn unsubscribe(&mut self, path: &Path) -> bool {
+        let len = self.subscribers.len();
+        self.subscribers.retain(|s| s != path);
+        self.subscribers.len() != len
+    }
+
+    /// Queue notifications about secrets that became overdue since
+    /// the last check. Return number of new events.
+    pub fn expired(&mut self, expiring: Vec<ExpiringSecret>) -> usize {
+        let mut notified = HashMap::with_capacity(expiring.len());
+        let mut events = vec![];
+        for e in expiring.into_iter().filter(|e| e.overdue) {
+            if self.notified.get(&e.secret.id) != Some(&e.expires) {
+                events.push(Event::SecretExpired {
+                    secret: e.secret.clone(),
+                    expires: e.expires,
+                });
+            }
+            notified.insert(e.secret.id, e.expires);
+        }
+
+        // Rotated secrets will be notified again
+        self.notified = notified;
+
+        let count = events.len();
+        self.pending.extend(events);
+        count
+    }
+
+    /// Queue notification about changes that couldn't be merged
+    pub fn conflict(&mut self, copy: &Path, item: Option<String>) {
+        self.pending.push(Event::MergeConflict {
+            copy: copy.to_string_lossy().into_owned(),
+            item: item,
+        });
+    }
+
+    /// Take pending events along with current subscribers
+    pub fn take_outbox(&mut self) -> Option<Outbox> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let events = self.pending.drain(..).collect();
+        match self.subscribers.is_empty() {
+            true => None,
+            false => Some(Outbox {
+                subscribers: self.subscribers.clone(),
+                events: events,
+            }),
+        }
+    }
+
+    /// Remove subscribers that failed to receive events
+    pub fn remove(&mut self, failed: &[PathBuf]) {
+        self.subscribers.retain(|s| !failed.contains(s));
+    }
+}
+
+impl Outbox {
+    /// Write events to every subscriber and return
+    /// paths of subscribers that failed to receive them
+    pub fn deliver(self) -> Vec<PathBuf> {
+        let mut lines = vec![];
+        for event in self.events.iter() {
+            if let Ok(mut line) = serde_json::to_vec(event) {
+                line.push(b'\n');
+                lines.extend(line);
+            }
+        }
+
+        self.subscribers
+            .into_iter()
+            .filter(|path| match write(path, &lines) {
+                Ok(_) => false,
+                Err(err) => {
+                    println!("Cannot notify {:?}: {}", path, err);
+                    true
+                }
+            })
+            .collect()
+    }
+}
+
+/// Check that path is socket owned by user of daemon
+pub fn check_socket(path: &Path) -> Result<(), Error> {
+    let meta = fs::metadata(path)?;
+    if !meta.file_type().is_socket() || meta.uid() != utils::process_uid()? {
+        return Err(Error::IncorrectReq);
+    }
+    Ok(())
+}
+
+/// Write to subscriber socket with timeout. Connect itself
+/// blocks while backlog of listener is full, so it's done
+/// in separate thread that is abandoned after timeout.
+fn write(path: &Path, data: &[u8]) -> Result<(), Error> {
+    check_socket(path)?;
+
+    let (tx, rx) = mpsc::channel();
+    let path = path.to_path_buf();
+    let data = data.to_vec();
+    thread::spawn(move || {
+        let res = UnixStream::connect(&path).and_then(|mut stream| {
+            stream.set_write_timeout(Some(Duration::from_millis(WRITE_TIMEOUT_MS)))?;
+            stream.write_all(&data)
+        });
+        let _ = tx.send(res);
+    });
+
+    match rx.recv_timeout(Duration::from_millis(WRITE_TIMEOUT_MS)) {
+        Ok(res) => Ok(res?),
+        Err(_) => Err(Error::IO(io::Error::new(io::ErrorKind::TimedOut, "timeout"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::{prelude::*, BufReader};
+    use std::os::unix::net::UnixListener;
+    use std::path::PathBuf;
+
+    use expiry::ExpiringSecret;
+    use notify::*;
+    use secret::{self, Secret};
+    use utils;
+
+    fn expiring(secret: &Secret, expires: i64) -> ExpiringSecret {
+        ExpiringSecret {
+            secret: secret.into(),
+            expires: expires,
+            overdue: true,
+        }
+    }
+
+    /// Deliver pending events as notify thread does
+    fn deliver(notifier: &mut Notifier) -> Vec<PathBuf> {
+        let failed = match notifier.take_outbox() {
+            Some(outbox) => outbox.deliver(),
+            None => vec![],
+        };
+        notifier.remove(&failed);
+        failed
+    }
+
+    #[test]
+    fn notifying() {
+        let path = env::temp_dir().join(format!("pasd_notify_{}.sock", utils::uid()));
+        let listener = UnixListener::bind(&path).unwrap();
+        let secret = secret::test_secret("Secret");
+
+        let mut notifier = Notifier::default();
+        notifier.subscribe(path.clone());
+        assert!(check_socket(&path).is_ok());
+        assert!(check_socket(&env::temp_dir()).is_err());
+        assert_eq!(notifier.expired(vec![expiring(&secret, 10)]), 1);
+        assert!(deliver(&mut notifier).is_empty());
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+        assert!(line.contains("\"event\":\"secret-expired\""));
+        assert!(line.contains(&secret.id));
+
+        // Only once for the same expiry
+        assert_eq!(notifier.expired(vec![expiring(&secret, 10)]), 0);
+        assert_eq!(notifier.expired(vec![]), 0);
+        assert_eq!(notifier.expired(vec![expiring(&secret, 20)]), 1);
+        deliver(&mut notifier);
+        listener.accept().unwrap();
+
+        // Gone subscriber is removed
+        drop(listener);
+        fs::remove_file(&path).unwrap();
+        notifier.expired(vec![expiring(&secret, 30)]);
+        assert_eq!(deliver(&mut notifier), vec![path.clone()]);
+        assert!(!notifier.unsubscribe(&path));
+    }
+}
@@ -0,0 +1,300 @@
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use url::percent_encoding::percent_decode;
+use url::Url;
+
+use errors::Error;
+use secure::SecretBuf;
+
+/// RFC 4648 base32 alphabet
+static BASE32_ALPH: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Hash function of HMAC
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OtpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl Default for OtpAlgorithm {
+    fn default() -> OtpAlgorithm {
+        OtpAlgorithm::Sha1
+    }
+}
+
+/// Parameters of TOTP secret (not secret themselves)
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+pub struct OtpParams {
+    #[serde(default)]
+    pub algorithm: OtpAlgorithm,
+    #[serde(default = "default_digits")]
+    pub digits: u32,
+    /// Seconds
+    #[serde(default = "default_period")]
+    pub period: u64,
+}
+
+impl Default for OtpParams {
+    fn default() -> OtpParams {
+        OtpParams {
+            algorithm: OtpAlgorithm::default(),
+            digits: default_digits(),
+            period: default_period(),
+        }
+    }
+}
+
+impl OtpParams {
+    /// Check if params are supported
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.digits < 6 || self.digits > 8 || self.period == 0 {
+            return Err(Error::InvalidOtp);
+        }
+        Ok(())
+    }
+}
+
+fn default_digits() -> u32 {
+    6
+}
+
+fn default_period() -> u64 {
+    30
+}
+
+/// Current one-time code
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OtpCode {
+    pub code: String,
+    /// Seconds until code expires
+    pub remaining: u64,
+    pub period: u64,
+}
+
+/// Imported otpauth uri
+#[derive(Debug, Clone)]
+pub struct OtpAuth {
+    /// Normalized base32 seed
+    pub seed: SecretBuf,
+    pub params: OtpParams,
+    /// Label without issuer prefix
+    pub account: Option<String>,
+    pub issuer: Option<String>,
+}
+
+/// Parse otpauth://totp/Issuer:account?secret=...&issuer=...
+/// &algorithm=SHA1&digits=6&period=30
+pub fn parse_uri(uri: &str) -> Result<OtpAuth, Error> {
+    let url = match Url::parse(uri) {
+        Ok(u) => u,
+        Err(_) => return Err(Error::InvalidOtp),
+    };
+    if url.scheme() != "otpauth" || url.host_str() != Some("totp") {
+        return Err(Error::InvalidOtp);
+    }
+
+    let mut seed = None;
+    let mut issuer = None;
+    let mut params = OtpParams::default();
+    for (name, value) in url.query_pairs() {
+        match name.as_ref() {
+            "secret" => seed = Some(normalize_seed(value.as_bytes())?),
+            "issuer" => issuer = Some(value.into_owned()),
+            "algorithm" => {
+                params.algorithm = match value.to_uppercase().as_str() {
+                    "SHA1" => OtpAlgorithm::Sha1,
+                    "SHA256" => OtpAlgorithm::Sha256,
+                    "SHA512" => OtpAlgorithm::Sha512,
+                    _ => return Err(Error::InvalidOtp),
+                }
+            }
+            "digits" => match value.parse() {
+                Ok(d) => params.digits = d,
+                Err(_) => return Err(Error::InvalidOtp),
+            },
+            "period" => match value.parse() {
+                Ok(p) => params.period = p,
+                Err(_) => return Err(Error::InvalidOtp),
+            },
+            _ => (),
+        }
+    }
+    params.validate()?;
+
+    // Label: "Issuer:account" or "account"
+    let label = percent_decode(url.path().trim_start_matches('/').as_bytes())
+        .decode_utf8_lossy()
+        .into_owned();
+    let account = match label.find(':') {
+        Some(i) => {
+            if issuer.is_none() {
+                issuer = Some(label[..i].trim().to_string());
+            }
+            label[i + 1..].trim().to_string()
+        }
+        None => label.trim().to_string(),
+    };
+
+    match seed {
+        Some(seed) => Ok(OtpAuth {
+            seed: seed,
+            params: params,
+            account: if account.is_empty() { None } else { Some(account) },
+            issuer: issuer.filter(|i| !i.is_empty()),
+        }),
+        None => Err(Error::InvalidOtp),
+    }
+}
+
+/// Get base32 seed in upper case without spaces
+/// and padding, checking that it can be decoded
+pub fn normalize_seed(seed: &[u8]) -> Result<SecretBuf, Error> {
+    let mut normalized = SecretBuf::with_capacity(seed.len());
+    for c in seed.iter() {
+        match *c {
+            b' ' | b'-' | b'=' => continue,
+            c => normalized.extend_from_slice(&[c.to_ascii_uppercase()]),
+        }
+    }
+
+    match base32_decode(&normalized) {
+        Some(ref key) if !key.is_empty() => Ok(normalized),
+        _ => Err(Error::InvalidOtp),
+    }
+}
+
+/// Get current TOTP code of base32 seed
+pub fn totp(seed: &[u8], params: &OtpParams, now: i64) -> Result<OtpCode, Error> {
+    params.validate()?;
+    let key = match base32_decode(seed) {
+        Some(k) => k,
+        None => return Err(Error::InvalidOtp),
+    };
+
+    let now = now.max(0) as u64;
+    Ok(OtpCode {
+        code: hotp(&key, now / params.period, params)?,
+        remaining: params.period - now % params.period,
+        period: params.period,
+    })
+}
+
+/// Get HOTP code (RFC 4226) of counter
+pub fn hotp(key: &[u8], counter: u64, params: &OtpParams) -> Result<String, Error> {
+    let msg = counter.to_be_bytes();
+    let hash = match params.algorithm {
+        OtpAlgorithm::Sha1 => {
+            let mut mac = Hmac::<Sha1>::new_varkey(key).map_err(|_| Error::InvalidOtp)?;
+            mac.input(&msg);
+            SecretBuf::new(mac.result().code().to_vec())
+        }
+        OtpAlgorithm::Sha256 => {
+            let mut mac = Hmac::<Sha256>::new_varkey(key).map_err(|_| Error::InvalidOtp)?;
+            mac.input(&msg);
+            SecretBuf::new(mac.result().code().to_vec())
+        }
+        OtpAlgorithm::Sha512 => {
+            let mut mac = Hmac::<Sha512>::new_varkey(key).map_err(|_| Error::InvalidOtp)?;
+            mac.input(&msg);
+            SecretBuf::new(mac.result().code().to_vec())
+        }
+    };
+
+    // Dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let bin = ((hash[offset] as u32 & 0x7f) << 24)
+        | ((hash[offset + 1] as u32) << 16)
+        | ((hash[offset + 2] as u32) << 8)
+        | (hash[offset + 3] as u32);
+    let code = bin % 10u32.pow(params.digits);
+
+    Ok(format!("{:0width$}", code, width = params.digits as usize))
+}
+
+/// Decode base32 (RFC 4648) without padding
+fn base32_decode(input: &[u8]) -> Option<SecretBuf> {
+    let mut output = SecretBuf::with_capacity(input.len() * 5 / 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for c in input.iter() {
+        let value = BASE32_ALPH.iter().position(|a| *a == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            output.extend_from_slice(&[(buffer >> bits) as u8]);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use otp::*;
+
+    // RFC 6238 seeds in base32
+    static SEED_SHA1: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+    static SEED_SHA256: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZA";
+
+    fn params(algorithm: OtpAlgorithm) -> OtpParams {
+        OtpParams {
+            algorithm: algorithm,
+            digits: 8,
+            period: 30,
+        }
+    }
+
+    #[test]
+    fn base32() {
+        assert_eq!(base32_decode(b"MZXW6YQ").unwrap().to_vec(), Vec::from("foob"));
+        assert!(base32_decode(b"MZXW1").is_none());
+        assert_eq!(normalize_seed(b"mzxw 6yq=").unwrap().to_vec(), Vec::from("MZXW6YQ"));
+        assert!(normalize_seed(b"").is_err());
+    }
+
+    #[test]
+    fn rfc_6238_codes() {
+        let sha1 = params(OtpAlgorithm::Sha1);
+        let code = totp(SEED_SHA1.as_bytes(), &sha1, 59).unwrap();
+        assert_eq!(code.code, "94287082".to_string());
+        assert_eq!(code.remaining, 1);
+        let code = totp(SEED_SHA1.as_bytes(), &sha1, 1111111109).unwrap();
+        assert_eq!(code.code, "07081804".to_string());
+
+        let sha256 = params(OtpAlgorithm::Sha256);
+        let code = totp(SEED_SHA256.as_bytes(), &sha256, 59).unwrap();
+        assert_eq!(code.code, "46119246".to_string());
+
+        let sha512_key = b"1234567890123456789012345678901234567890123456789012345678901234";
+        let code = hotp(sha512_key, 1, &params(OtpAlgorithm::Sha512)).unwrap();
+        assert_eq!(code, "90693936".to_string());
+    }
+
+    #[test]
+    fn parsing_uri() {
+        let auth = parse_uri(
+            "otpauth://totp/Example:alice%40example.com?secret=JBSWY3DPEHPK3PXP\
+             &issuer=Example&algorithm=SHA256&digits=8&period=60",
+        ).unwrap();
+        assert_eq!(auth.seed.to_vec(), Vec::from("JBSWY3DPEHPK3PXP"));
+        assert_eq!(auth.account, Some("alice@example.com".to_string()));
+        assert_eq!(auth.issuer, Some("Example".to_string()));
+        assert_eq!(auth.params.algorithm, OtpAlgorithm::Sha256);
+        assert_eq!(auth.params.digits, 8);
+        assert_eq!(auth.params.period, 60);
+
+        let auth = parse_uri("otpauth://totp/Bank:bob?secret=JBSWY3DPEHPK3PXP").unwrap();
+        assert_eq!(auth.issuer, Some("Bank".to_string()));
+        assert_eq!(auth.params, OtpParams::default());
+
+        assert!(parse_uri("otpauth://hotp/Bank:bob?secret=JBSWY3DPEHPK3PXP").is_err());
+        assert!(parse_uri("otpauth://totp/Bank:bob?secret=1111").is_err());
+        assert!(parse_uri("otpauth://totp/Bank:bob?secret=JBSWY3DP&digits=12").is_err());
+    }
+}
@@ -0,0 +1,418 @@
+use std::fs;
+use std::path::Path;
+
+use rand::{OsRng, Rng};
+
+use errors::Error;
+use secure::SecretString;
+
+/// Max length of generated password
+const MAX_LENGTH: usize = 1024;
+
+/// Max number of passphrase words
+const MAX_WORDS: usize = 64;
+
+/// Chars that are easy to confuse
+static AMBIGUOUS: &str = "Il1|O0o";
+
+static LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+static UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+static DIGITS: &str = "0123456789";
+static SYMBOLS: &str = "!#$%&()*+,-./:;<=>?@[]^_{|}~";
+
+/// Letters of pronounceable passwords
+static CONSONANTS: &str = "bcdfghjklmnprstvwz";
+static VOWELS: &str = "aeiou";
+
+/// Built-in passphrase words, used if there is no
+/// configured diceware word list
+static WORDS: [&str; 474] = [
+    "able", "acid", "aged", "also", "area", "away", "baby", "back", "ball", "band", "bank", "base",
+    "bath", "bear", "beat", "been", "beer", "bell", "belt", "best", "bill", "bird", "blow", "blue",
+    "boat", "body", "bond", "bone", "book", "boom", "born", "boss", "both", "bowl", "bulk", "burn",
+    "bush", "busy", "cafe", "cake", "call", "calm", "came", "camp", "card", "care", "cart", "case",
+    "cash", "cast", "cell", "chat", "chip", "city", "clay", "club", "coal", "coat", "code", "cold",
+    "come", "cook", "cool", "cope", "copy", "core", "cost", "crew", "crop", "dark", "data", "date",
+    "dawn", "days", "deal", "dear", "debt", "deep", "deny", "desk", "dial", "diet", "disk", "dock",
+    "door", "dose", "down", "draw", "drew", "drop", "drum", "dual", "duke", "dust", "duty", "each",
+    "earn", "ease", "east", "easy", "edge", "else", "even", "ever", "exit", "face", "fact", "fair",
+    "fall", "farm", "fast", "fate", "fear", "feed", "feel", "feet", "fell", "felt", "file", "fill",
+    "film", "find", "fine", "fire", "firm", "fish", "five", "flat", "flow", "food", "foot", "ford",
+    "form", "fort", "four", "free", "from", "fuel", "full", "fund", "gain", "game", "gate", "gave",
+    "gear", "gift", "girl", "give", "glad", "goal", "goes", "gold", "golf", "gone", "good", "gray",
+    "grew", "grey", "grow", "gulf", "hair", "half", "hall", "hand", "hang", "hard", "harm", "have",
+    "head", "hear", "heat", "held", "help", "here", "hero", "high", "hill", "hire", "hold", "hole",
+    "home", "hope", "host", "hour", "huge", "hung", "hunt", "hurt", "idea", "inch", "into", "iron",
+    "item", "jack", "jazz", "join", "jump", "jury", "just", "keen", "keep", "kept", "kick", "kind",
+    "king", "knee", "knew", "know", "lack", "lady", "laid", "lake", "land", "lane", "last", "late",
+    "lead", "left", "less", "life", "lift", "like", "line", "link", "list", "live", "load", "loan",
+    "lock", "long", "look", "lose", "loss", "lost", "love", "luck", "made", "mail", "main", "make",
+    "male", "many", "mark", "mass", "meal", "mean", "meat", "meet", "menu", "mere", "mild", "mile",
+    "milk", "mill", "mind", "mine", "miss", "mode", "mood", "moon", "more", "most", "move", "much",
+    "must", "name", "near", "neck", "need", "news", "next", "nice", "nine", "none", "nose", "note",
+    "okay", "once", "only", "open", "oral", "over", "pace", "pack", "page", "paid", "pain", "pair",
+    "palm", "park", "part", "pass", "past", "path", "peak", "pick", "pink", "pipe", "plan", "play",
+    "plot", "plus", "poll", "pool", "poor", "port", "post", "pull", "pure", "push", "race", "rail",
+    "rain", "rank", "rare", "rate", "read", "real", "rear", "rely", "rent", "rest", "rice", "rich",
+    "ride", "ring", "rise", "risk", "road", "rock", "role", "roll", "roof", "room", "root", "rose",
+    "rule", "rush", "safe", "said", "sake", "sale", "salt", "same", "sand", "save", "seat", "seed",
+    "seek", "seem", "seen", "self", "sell", "send", "sent", "ship", "shop", "shot", "show", "shut",
+    "side", "sign", "site", "size", "skin", "slip", "slow", "snow", "soft", "soil", "sold", "sole",
+    "some", "song", "soon", "sort", "soul", "spot", "star", "stay", "step", "stop", "such", "suit",
+    "sure", "take", "tale", "talk", "tall", "tank", "tape", "task", "team", "tell", "tend", "term",
+    "test", "text", "than", "that", "them", "then", "they", "thin", "this", "thus", "tide", "tied",
+    "tier", "till", "time", "tiny", "told", "tone", "took", "tool", "tour", "town", "tree", "trip",
+    "true", "tune", "turn", "twin", "type", "unit", "upon", "used", "user", "vast", "very", "view",
+    "vote", "wage", "wait", "wake", "walk", "wall", "want", "ward", "warm", "wash", "wave", "ways",
+    "weak", "wear", "week", "well", "went", "were", "west", "what", "when", "whom", "wide", "wife",
+    "wild", "will", "wind", "wine", "wing", "wire", "wise", "wish", "with", "wood", "word", "wore",
+    "work", "yard", "year", "your", "zero", "zone",
+];
+
+/// Class of password chars
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CharClass {
+    Lower,
+    Upper,
+    Digits,
+    Symbols,
+}
+
+impl CharClass {
+    fn chars(&self) -> &'static str {
+        match self {
+            CharClass::Lower => LOWER,
+            CharClass::Upper => UPPER,
+            CharClass::Digits => DIGITS,
+            CharClass::Symbols => SYMBOLS,
+        }
+    }
+}
+
+/// How password is generated
+///
+/// Random - random chars of selected classes
+/// Pronounceable - alternating consonants and vowels
+/// Passphrase - random words (diceware)
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordMode {
+    Random,
+    Pronounceable,
+    Passphrase,
+}
+
+impl Default for PasswordMode {
+    fn default() -> PasswordMode {
+        PasswordMode::Random
+    }
+}
+
+/// Arguments of password generation
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasswordArgs {
+    #[serde(default)]
+    pub mode: PasswordMode,
+    /// Number of chars (random and pronounceable modes)
+    #[serde(default = "default_length")]
+    pub length: usize,
+    #[serde(default = "default_classes")]
+    pub classes: Vec<CharClass>,
+    /// Chars that shouldn't be used
+    #[serde(default)]
+    pub exclude: String,
+    #[serde(default)]
+    pub exclude_ambiguous: bool,
+    /// At least one char of each class
+    #[serde(default)]
+    pub require_each: bool,
+    /// Number of passphrase words
+    #[serde(default = "default_words")]
+    pub words: usize,
+    #[serde(default = "default_separator")]
+    pub separator: String,
+}
+
+impl Default for PasswordArgs {
+    fn default() -> PasswordArgs {
+        PasswordArgs {
+            mode: PasswordMode::default(),
+            length: default_length(),
+            classes: default_classes(),
+            exclude: String::new(),
+            exclude_ambiguous: false,
+            require_each: false,
+            words: default_words(),
+            separator: default_separator(),
+        }
+    }
+}
+
+fn default_length() -> usize {
+    20
+}
+
+fn default_classes() -> Vec<CharClass> {
+    vec![CharClass::Lower, CharClass::Upper, CharClass::Digits]
+}
+
+fn default_words() -> usize {
+    6
+}
+
+fn default_separator() -> String {
+    " ".to_string()
+}
+
+/// Generated password with estimated entropy (bits)
+#[derive(Debug, Clone, Serialize)]
+pub struct Password {
+    pub value: SecretString,
+    pub entropy: f64,
+}
+
+/// Generate password. Passphrase words are taken from
+/// provided word list file or from built-in list.
+pub fn generate(args: &PasswordArgs, wordlist: Option<&Path>) -> Result<Password, Error> {
+    match args.mode {
+        PasswordMode::Random => random(args),
+        PasswordMode::Pronounceable => pronounceable(args),
+        PasswordMode::Passphrase => match wordlist {
+            Some(path) => passphrase(args, &read_wordlist(path)?),
+            None => passphrase(args, &WORDS),
+        },
+    }
+}
+
+/// Random chars of selected classes
+fn random(args: &PasswordArgs) -> Result<Password, Error> {
+    if args.length == 0 || args.length > MAX_LENGTH {
+        return Err(Error::IncorrectReq);
+    }
+
+    // Allowed chars of every class
+    let mut classes: Vec<Vec<char>> = vec![];
+    for class in args.classes.iter() {
+        let chars = allowed_chars(class.chars(), args);
+        if chars.is_empty() {
+            return Err(Error::IncorrectReq);
+        }
+        if !classes.contains(&chars) {
+            classes.push(chars);
+        }
+    }
+    if classes.is_empty() || (args.require_each && args.length < classes.len()) {
+        return Err(Error::IncorrectReq);
+    }
+    let alphabet: Vec<char> = classes.iter().flat_map(|c| c.iter().cloned()).collect();
+
+    // Retry until every class is present to keep
+    // passwords uniformly distributed
+    let mut rng = match OsRng::new() {
+        Ok(r) => r,
+        Err(_) => return Err(Error::Internal),
+    };
+    let value = loop {
+        let value: String = (0..args.length)
+            .map(|_| alphabet[rng.gen_range(0, alphabet.len())])
+            .collect();
+        let has_each = classes
+            .iter()
+            .all(|class| value.chars().any(|c| class.contains(&c)));
+        if !args.require_each || has_each {
+            break value;
+        }
+    };
+
+    // Share of strings with each class (inclusion-exclusion)
+    let sizes: Vec<usize> = classes.iter().map(|c| c.len()).collect();
+    let valid_share = match args.require_each {
+        true => required_share(&sizes, alphabet.len(), args.length),
+        false => 1.0,
+    };
+    let entropy = args.length as f64 * (alphabet.len() as f64).log2() + valid_share.log2();
+
+    Ok(Password {
+        value: SecretString::new(value),
+        entropy: entropy,
+    })
+}
+
+/// Alternating consonants and vowels
+fn pronounceable(args: &PasswordArgs) -> Result<Password, Error> {
+    if args.length == 0 || args.length > MAX_LENGTH {
+        return Err(Error::IncorrectReq);
+    }
+    let consonants = allowed_chars(CONSONANTS, args);
+    let vowels = allowed_chars(VOWELS, args);
+    if consonants.is_empty() || vowels.is_empty() {
+        return Err(Error::IncorrectReq);
+    }
+
+    let mut rng = match OsRng::new() {
+        Ok(r) => r,
+        Err(_) => return Err(Error::Internal),
+    };
+    let mut value = String::with_capacity(args.length);
+    let mut entropy = 0.0;
+    for i in 0..args.length {
+        let letters = match i % 2 {
+            0 => &consonants,
+            _ => &vowels,
+        };
+        value.push(letters[rng.gen_range(0, letters.len())]);
+        entropy += (letters.len() as f64).log2();
+    }
+
+    Ok(Password {
+        value: SecretString::new(value),
+        entropy: entropy,
+    })
+}
+
+/// Random words from list
+fn passphrase<T: AsRef<str>>(args: &PasswordArgs, words: &[T]) -> Result<Password, Error> {
+    if args.words == 0 || args.words > MAX_WORDS || words.len() < 2 {
+        return Err(Error::IncorrectReq);
+    }
+
+    let mut rng = match OsRng::new() {
+        Ok(r) => r,
+        Err(_) => return Err(Error::Internal),
+    };
+    let chosen: Vec<&str> = (0..args.words)
+        .map(|_| words[rng.gen_range(0, words.len())].as_ref())
+        .collect();
+
+    Ok(Password {
+        value: SecretString::new(chosen.join(&args.separator)),
+        entropy: args.words as f64 * (words.len() as f64).log2(),
+    })
+}
+
+/// Read diceware word list: one word per line,
+/// optionally prefixed with dice numbers
+fn read_wordlist(path: &Path) -> Result<Vec<String>, Error> {
+    let content = fs::read_to_string(path)?;
+    let mut words: Vec<String> = content
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .map(|word| word.to_string())
+        .collect();
+    words.sort();
+    words.dedup();
+
+    match words.len() {
+        n if n < 2 => Err(Error::IncorrectConf),
+        _ => Ok(words),
+    }
+}
+
+/// Get chars of set without excluded ones
+fn allowed_chars(chars: &str, args: &PasswordArgs) -> Vec<char> {
+    chars
+        .chars()
+        .filter(|c| !args.exclude.contains(*c))
+        .filter(|c| !args.exclude_ambiguous || !AMBIGUOUS.contains(*c))
+        .collect()
+}
+
+/// Get share of strings of provided length that have at least
+/// one char of every class (classes are disjoint)
+fn required_share(sizes: &[usize], total: usize, length: usize) -> f64 {
+    let mut share = 0.0;
+    for subset in 0..(1usize << sizes.len()) {
+        // Strings without chars of classes in subset
+        let missing: usize = (0..sizes.len())
+            .filter(|&i| subset & (1 << i) != 0)
+            .map(|i| sizes[i])
+            .sum();
+        let term = (1.0 - missing as f64 / total as f64).powi(length as i32);
+        match subset.count_ones() % 2 {
+            0 => share += term,
+            _ => share -= term,
+        }
+    }
+    share
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use password::*;
+    use utils;
+
+    #[test]
+    fn random_passwords() {
+        let mut args = PasswordArgs::default();
+        args.length = 32;
+        args.classes = vec![CharClass::Digits, CharClass::Symbols];
+        args.exclude = "0123".to_string();
+        args.require_each = true;
+
+        let password = generate(&args, None).unwrap();
+        let value = password.value.as_str();
+        assert_eq!(value.len(), 32);
+        assert!(value.chars().all(|c| !"0123".contains(c)));
+        assert!(value.chars().any(|c| DIGITS.contains(c)));
+        assert!(value.chars().any(|c| SYMBOLS.contains(c)));
+        assert!(password.entropy > 160.0 && password.entropy < 32.0 * 35f64.log2());
+
+        // Classes can't be satisfied
+        args.length = 1;
+        assert!(generate(&args, None).is_err());
+        args.classes = vec![CharClass::Digits];
+        args.exclude = DIGITS.to_string();
+        assert!(generate(&args, None).is_err());
+    }
+
+    #[test]
+    fn entropy_of_required_classes() {
+        // Two classes of one char, length 2: "ab" and "ba" of 4
+        assert!((required_share(&[1, 1], 2, 2) - 0.5).abs() < 1e-9);
+        assert!((required_share(&[10], 10, 5) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pronounceable_passwords() {
+        let mut args = PasswordArgs::default();
+        args.mode = PasswordMode::Pronounceable;
+        args.length = 9;
+        args.exclude_ambiguous = true;
+
+        let password = generate(&args, None).unwrap();
+        let value: Vec<char> = password.value.as_str().chars().collect();
+        assert_eq!(value.len(), 9);
+        assert!(CONSONANTS.contains(value[0]) && VOWELS.contains(value[1]));
+        assert!(!value.contains(&'o') && !value.contains(&'l'));
+        assert!((password.entropy - (5.0 * 17f64.log2() + 4.0 * 4f64.log2())).abs() < 1e-9);
+    }
+
+    #[test]
+    fn passphrases() {
+        let mut args = PasswordArgs::default();
+        args.mode = PasswordMode::Passphrase;
+        args.words = 4;
+        args.separator = "-".to_string();
+
+        let password = generate(&args, None).unwrap();
+        assert_eq!(password.value.as_str().split('-').count(), 4);
+        assert!((password.entropy - 4.0 * (WORDS.len() as f64).log2()).abs() < 1e-9);
+
+        // Diceware list
+        let path = env::temp_dir().join(format!("pasd_words_{}", utils::uid()));
+        fs::write(&path, "11111\talpha\n11112\tbeta\n\n11113 gamma\n").unwrap();
+        let password = generate(&args, Some(&path)).unwrap();
+        assert!(password
+            .value
+            .as_str()
+            .split('-')
+            .all(|w| w == "alpha" || w == "beta" || w == "gamma"));
+        assert!((password.entropy - 4.0 * 3f64.log2()).abs() < 1e-9);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,339 @@
+use chrono::NaiveDate;
+use regex::Regex;
+
+use errors::Error;
+use secret::Secret;
+
+/// Seconds in one day
+const DAY: i64 = 86400;
+
+/// Field of secret checked by query term
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Field {
+    /// Id, name, url or login
+    Any,
+    Id,
+    Name,
+    Url,
+    Login,
+    Tag,
+    Folder,
+    Date,
+    Modified,
+}
+
+impl Field {
+    fn from_str(name: &str) -> Option<Field> {
+        match name {
+            "id" => Some(Field::Id),
+            "name" => Some(Field::Name),
+            "url" => Some(Field::Url),
+            "login" => Some(Field::Login),
+            "tag" => Some(Field::Tag),
+            "folder" => Some(Field::Folder),
+            "date" => Some(Field::Date),
+            "modified" => Some(Field::Modified),
+            _ => None,
+        }
+    }
+}
+
+/// How term value is matched
+#[derive(Debug)]
+enum Matcher {
+    /// Case-insensitive substring
+    Contains(String),
+    /// Case-insensitive whole value
+    Exact(String),
+    Regex(Regex),
+    /// Timestamps range (inclusive)
+    Range(Option<i64>, Option<i64>),
+}
+
+/// Single query term
+#[derive(Debug)]
+struct Term {
+    field: Field,
+    matcher: Matcher,
+    negated: bool,
+}
+
+/// Parsed secrets query
+///
+/// Query is a list of terms, all of them should match:
+///   github          - substring of id, name, url or login
+///   name:github     - substring of field (id, name, url, login, tag, folder)
+///   name:=GitHub    - whole value
+///   url:/^https/    - regex
+///   -tag:old        - negation (also '!')
+///   "-1 day"        - quoted parts are matched literally
+///   date:2019-01-01..2019-03-01, date:>2019-01-01,
+///   modified:..2019-03-01 - dates ranges (UTC days)
+#[derive(Debug, Default)]
+pub struct Query {
+    terms: Vec<Term>,
+}
+
+impl Query {
+    /// Parse query string, terms are separated by spaces.
+    /// Quoted parts may contain spaces: name:"my bank"
+    pub fn parse(query: &str) -> Result<Query, Error> {
+        let mut terms = vec![];
+        let mut term = String::new();
+        let mut quoted = false;
+
+        // Quotes are kept for terms parsing
+        for c in query.chars() {
+            match c {
+                '"' => {
+                    quoted = !quoted;
+                    term.push(c);
+                }
+                c if c.is_whitespace() && !quoted => {
+                    if !term.is_empty() {
+                        terms.push(term.clone());
+                        term.clear();
+                    }
+                }
+                c => term.push(c),
+            }
+        }
+        if quoted {
+            return Err(Error::InvalidQuery);
+        }
+        if !term.is_empty() {
+            terms.push(term);
+        }
+
+        Query::from_terms(&terms)
+    }
+
+    /// Parse list of already separated terms
+    pub fn from_terms(terms: &[String]) -> Result<Query, Error> {
+        let mut query = Query::default();
+        for term in terms.iter() {
+            if !term.is_empty() {
+                query.terms.push(Query::parse_term(term)?);
+            }
+        }
+
+        Ok(query)
+    }
+
+    /// Check if secret matches all terms
+    pub fn matches(&self, secret: &Secret) -> bool {
+        self.terms.iter().all(|term| {
+            let matched = match term.field {
+                Field::Date => Query::match_date(&term.matcher, Some(secret.date)),
+                Field::Modified => Query::match_date(&term.matcher, secret.modified),
+                _ => Query::values(term.field, secret)
+                    .iter()
+                    .any(|v| Query::match_text(&term.matcher, v)),
+            };
+            matched != term.negated
+        })
+    }
+
+    /// Parse single term. Negation, qualifier and matcher
+    /// prefixes are recognized outside of quotes only.
+    fn parse_term(term: &str) -> Result<Term, Error> {
+        // Negation
+        let (negated, term) = match term.len() > 1 && (term.starts_with('-') || term.starts_with('!')) {
+            true => (true, &term[1..]),
+            false => (false, term),
+        };
+
+        // Field qualifier
+        let (field, value) = match term.find(':') {
+            Some(i) => match Field::from_str(&term[..i]) {
+                Some(f) => (f, &term[i + 1..]),
+                None => (Field::Any, term),
+            },
+            None => (Field::Any, term),
+        };
+
+        let matcher = match field {
+            Field::Date | Field::Modified => Query::parse_range(&unquote(value))?,
+            _ => Query::parse_text(value)?,
+        };
+
+        Ok(Term {
+            field: field,
+            matcher: matcher,
+            negated: negated,
+        })
+    }
+
+    /// Parse text matcher
+    fn parse_text(value: &str) -> Result<Matcher, Error> {
+        if value.starts_with('=') {
+            return Ok(Matcher::Exact(unquote(&value[1..]).to_lowercase()));
+        }
+        if value.len() > 1 && value.starts_with('/') && value.ends_with('/') {
+            return match Regex::new(&unquote(&value[1..value.len() - 1])) {
+                Ok(r) => Ok(Matcher::Regex(r)),
+                Err(_) => Err(Error::InvalidQuery),
+            };
+        }
+
+        Ok(Matcher::Contains(unquote(value).to_lowercase()))
+    }
+
+    /// Parse dates range: DATE, FROM..TO, FROM.., ..TO, >DATE, <DATE
+    fn parse_range(value: &str) -> Result<Matcher, Error> {
+        if value.starts_with('>') {
+            let start = Query::parse_day(&value[1..])? + DAY;
+            return Ok(Matcher::Range(Some(start), None));
+        }
+        if value.starts_with('<') {
+            let end = Query::parse_day(&value[1..])? - 1;
+            return Ok(Matcher::Range(None, Some(end)));
+        }
+
+        match value.find("..") {
+            Some(i) => {
+                let (from, to) = (&value[..i], &value[i + 2..]);
+                let start = match from.is_empty() {
+                    true => None,
+                    false => Some(Query::parse_day(from)?),
+                };
+                let end = match to.is_empty() {
+                    true => None,
+                    false => Some(Query::parse_day(to)? + DAY - 1),
+                };
+                Ok(Matcher::Range(start, end))
+            }
+            None => {
+                let start = Query::parse_day(value)?;
+                Ok(Matcher::Range(Some(start), Some(start + DAY - 1)))
+            }
+        }
+    }
+
+    /// Get timestamp of day start (UTC)
+    fn parse_day(value: &str) -> Result<i64, Error> {
+        match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            Ok(d) => Ok(d.and_hms(0, 0, 0).timestamp()),
+            Err(_) => Err(Error::InvalidQuery),
+        }
+    }
+
+    /// Get values of secret for text field
+    fn values(field: Field, secret: &Secret) -> Vec<&str> {
+        let mut values = Vec::with_capacity(4);
+
+        if field == Field::Any || field == Field::Id {
+            values.push(secret.id.as_str());
+        }
+        if field == Field::Any || field == Field::Name {
+            values.push(secret.name.as_str());
+        }
+        if field == Field::Any || field == Field::Url {
+            if let Some(ref url) = secret.url {
+                values.push(url.as_str());
+            }
+        }
+        if field == Field::Any || field == Field::Login {
+            if let Some(ref login) = secret.login {
+                values.push(login.as_str());
+            }
+        }
+        if field == Field::Tag {
+            values.extend(secret.tags.iter().map(|t| t.as_str()));
+        }
+        if field == Field::Folder {
+            if let Some(ref folder) = secret.folder {
+                values.push(folder.as_str());
+            }
+        }
+
+        values
+    }
+
+    fn match_text(matcher: &Matcher, value: &str) -> bool {
+        match matcher {
+            Matcher::Contains(v) => value.to_lowercase().contains(v.as_str()),
+            Matcher::Exact(v) => value.to_lowercase() == *v,
+            Matcher::Regex(r) => r.is_match(value),
+            Matcher::Range(_, _) => false,
+        }
+    }
+
+    fn match_date(matcher: &Matcher, date: Option<i64>) -> bool {
+        match (matcher, date) {
+            (Matcher::Range(start, end), Some(d)) => {
+                start.map_or(true, |s| d >= s) && end.map_or(true, |e| d <= e)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Remove quotes from term value
+fn unquote(value: &str) -> String {
+    value.replace('"', "")
+}
+
+#[cfg(test)]
+mod tests {
+    use query::*;
+    use secret::{self, Secret};
+
+    fn test_secret(name: &str, url: Option<&str>, tags: &[&str], date: &str) -> Secret {
+        let mut secret = secret::test_secret(name);
+        secret.url = url.map(|u| u.to_string());
+        secret.login = Some("user".to_string());
+        secret.tags = tags.iter().map(|t| t.to_string()).collect();
+        secret.date = Query::parse_day(date).unwrap() + 3600;
+        secret
+    }
+
+    #[test]
+    fn parsing() {
+        let query = Query::parse("name:\"my bank\" -tag:old url:/^https/").unwrap();
+        assert_eq!(query.terms.len(), 3);
+        assert_eq!(query.terms[0].field, Field::Name);
+        assert!(!query.terms[0].negated);
+        assert!(query.terms[1].negated);
+
+        // Quoted prefixes are literal
+        let query = Query::parse("\"-old\" \"name:=x\"").unwrap();
+        assert!(!query.terms[0].negated);
+        assert_eq!(query.terms[1].field, Field::Any);
+
+        // Unknown qualifier is a part of value
+        let query = Query::parse("https://github.com").unwrap();
+        assert_eq!(query.terms[0].field, Field::Any);
+
+        assert!(Query::parse("name:\"unclosed").is_err());
+        assert!(Query::parse("url:/(/").is_err());
+        assert!(Query::parse("date:yesterday").is_err());
+    }
+
+    #[test]
+    fn matching() {
+        let github = test_secret("GitHub", Some("https://github.com"), &["dev"], "2019-02-10");
+        let gitlab = test_secret("GitLab", Some("http://gitlab.com"), &["dev", "old"], "2019-03-10");
+
+        let matches = |q: &str| {
+            let query = Query::parse(q).unwrap();
+            (query.matches(&github), query.matches(&gitlab))
+        };
+
+        assert_eq!(matches("git"), (true, true));
+        assert_eq!(matches("name:=github"), (true, false));
+        assert_eq!(matches("name:=git"), (false, false));
+        assert_eq!(matches("url:/^https:/"), (true, false));
+        assert_eq!(matches("tag:dev -tag:old"), (true, false));
+        assert_eq!(matches("!login:user"), (false, false));
+        assert_eq!(matches("\"-lab\""), (false, false));
+        assert_eq!(matches("name:=\"github\""), (true, false));
+        assert_eq!(matches("date:2019-03-01.."), (false, true));
+        assert_eq!(matches("date:..2019-02-10"), (true, false));
+        assert_eq!(matches("date:2019-02-10"), (true, false));
+        assert_eq!(matches("date:>2019-02-10"), (false, true));
+        assert_eq!(matches("date:<2019-02-10"), (false, false));
+        assert_eq!(matches("modified:2019-01-01.."), (false, false));
+        assert_eq!(matches(""), (true, true));
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+use secret::{Secret, SecretInfo};
+
+/// Score of matched char
+const CHAR_SCORE: i64 = 1;
+
+/// Bonus for char at the start of word
+const BOUNDARY_BONUS: i64 = 8;
+
+/// Bonus for char right after previous matched one
+const CONSECUTIVE_BONUS: i64 = 5;
+
+/// Max bonus for secret used right now
+const RECENCY_BONUS: i64 = 30;
+
+/// Seconds in one day
+const DAY: i64 = 86400;
+
+/// Result of fuzzy matching of text
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    /// Indexes of matched chars
+    pub positions: Vec<usize>,
+}
+
+/// Matched chars of secret field
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Highlight {
+    pub field: String,
+    pub positions: Vec<usize>,
+}
+
+/// Found secret with its score and highlights
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    pub secret: SecretInfo,
+    pub score: i64,
+    pub highlights: Vec<Highlight>,
+}
+
+/// Search secrets by fuzzy matching of every word of query
+/// with name, url or login. Results are ordered by score,
+/// recently used secrets get bonus.
+pub fn search(
+    secrets: &[Secret],
+    query: &str,
+    used: &HashMap<String, i64>,
+    now: i64,
+) -> Vec<SearchResult> {
+    let words: Vec<&str> = query.split_whitespace().collect();
+    if words.is_empty() {
+        return vec![];
+    }
+
+    let mut results = vec![];
+    for secret in secrets.iter() {
+        let fields = [
+            ("name", Some(&secret.name), 3),
+            ("url", secret.url.as_ref(), 2),
+            ("login", secret.login.as_ref(), 1),
+        ];
+        let mut score = 0;
+        let mut highlights: Vec<Highlight> = vec![];
+
+        // Every word should match some field
+        let all_matched = words.iter().all(|word| {
+            let best = fields
+                .iter()
+                .filter_map(|(name, text, weight)| {
+                    text.and_then(|t| fuzzy_match(word, t))
+                        .map(|m| (name, m.score * weight, m.positions))
+                })
+                .max_by_key(|(_, s, _)| *s);
+
+            match best {
+                Some((field, s, positions)) => {
+                    score += s;
+                    match highlights.iter_mut().find(|h| h.field == *field) {
+                        Some(h) => {
+                            h.positions.extend(positions);
+                            h.positions.sort();
+                            h.positions.dedup();
+                        }
+                        None => highlights.push(Highlight {
+                            field: field.to_string(),
+                            positions: positions,
+                        }),
+                    }
+                    true
+                }
+                None => false,
+            }
+        });
+        if !all_matched {
+            continue;
+        }
+
+        // Recency of use
+        if let Some(last_used) = used.get(&secret.id) {
+            let days = (now - last_used).max(0) / DAY;
+            score += RECENCY_BONUS / (1 + days);
+        }
+
+        results.push(SearchResult {
+            secret: secret.into(),
+            score: score,
+            highlights: highlights,
+        });
+    }
+
+    results.sort_by(|a, b| b.score.cmp(&a.score).then(a.secret.name.cmp(&b.secret.name)));
+    results
+}
+
+/// Find best case-insensitive subsequence match of pattern in text.
+/// Matches at word starts and consecutive matches score higher.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<FuzzyMatch> {
+    let pattern: Vec<char> = pattern.chars().flat_map(|c| c.to_lowercase()).collect();
+    let text: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = text
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    let (m, n) = (pattern.len(), text.len());
+    if m == 0 || m > n {
+        return None;
+    }
+
+    // Score of char depends on its position in word
+    let char_score = |j: usize| -> i64 {
+        let boundary = j == 0
+            || !text[j - 1].is_alphanumeric()
+            || (text[j - 1].is_lowercase() && text[j].is_uppercase());
+        match boundary {
+            true => CHAR_SCORE + BOUNDARY_BONUS,
+            false => CHAR_SCORE,
+        }
+    };
+
+    // best[i][j] - best score of pattern[..=i] with pattern[i] at text[j]
+    let none = i64::min_value();
+    let mut best = vec![vec![none; n]; m];
+    let mut prev = vec![vec![0usize; n]; m];
+    for j in 0..n {
+        if lower[j] == pattern[0] {
+            best[0][j] = char_score(j);
+        }
+    }
+    for i in 1..m {
+        // Best score of previous char placed before j - 1
+        let mut gap_best = none;
+        let mut gap_index = 0;
+        for j in 0..n {
+            if j >= 2 && best[i - 1][j - 2] > gap_best {
+                gap_best = best[i - 1][j - 2];
+                gap_index = j - 2;
+            }
+            if lower[j] != pattern[i] {
+                continue;
+            }
+            if gap_best > none {
+                best[i][j] = gap_best + char_score(j);
+                prev[i][j] = gap_index;
+            }
+            if j >= 1 && best[i - 1][j - 1] > none {
+                let score = best[i - 1][j - 1] + char_score(j) + CONSECUTIVE_BONUS;
+                if score > best[i][j] {
+                    best[i][j] = score;
+                    prev[i][j] = j - 1;
+                }
+            }
+        }
+    }
+
+    // Find the best end and restore positions
+    let (end, score) = best[m - 1]
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| **s > none)
+        .max_by_key(|(_, s)| **s)
+        .map(|(j, s)| (j, *s))?;
+    let mut positions = vec![0; m];
+    let mut j = end;
+    for i in (0..m).rev() {
+        positions[i] = j;
+        j = prev[i][j];
+    }
+
+    Some(FuzzyMatch {
+        score: score,
+        positions: positions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use search::*;
+    use secret::{self, Secret};
+
+    fn test_secret(name: &str, url: &str) -> Secret {
+        let mut secret = secret::test_secret(name);
+        secret.url = Some(url.to_string());
+        secret
+    }
+
+    #[test]
+    fn fuzzy_matching() {
+        assert_eq!(fuzzy_match("xyz", "GitHub"), None);
+        assert_eq!(fuzzy_match("gh", "GitHub").unwrap().positions, vec![0, 3]);
+
+        // Consecutive and word start matches are better
+        let consecutive = fuzzy_match("git", "GitHub").unwrap();
+        let scattered = fuzzy_match("git", "Gadgetiety").unwrap();
+        assert_eq!(consecutive.positions, vec![0, 1, 2]);
+        assert!(consecutive.score > scattered.score);
+        assert_eq!(fuzzy_match("ma", "my-mail").unwrap().positions, vec![3, 4]);
+    }
+
+    #[test]
+    fn ranking() {
+        let secrets = vec![
+            test_secret("Gitea", "https://gitea.local"),
+            test_secret("GitHub", "https://github.com"),
+            test_secret("Mail", "https://mail.com"),
+        ];
+        let mut used = HashMap::new();
+
+        let results = search(&secrets, "gh", &used, 0);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].secret.name, "GitHub".to_string());
+        assert_eq!(results[0].highlights[0].field, "name".to_string());
+        assert_eq!(results[0].highlights[0].positions, vec![0, 3]);
+
+        // Recently used secret goes first
+        let results = search(&secrets, "git", &used, 0);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].secret.name, "GitHub".to_string());
+        used.insert(secrets[0].id.clone(), 0);
+        let results = search(&secrets, "git", &used, 0);
+        assert_eq!(results[0].secret.name, "Gitea".to_string());
+
+        // Words may match different fields
+        let results = search(&secrets, "git .com", &used, 0);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].highlights.len(), 2);
+    }
+}
@@ -2,37 +2,202 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
 
-use chrono::Local;
+use aes_gcm::aead::Aead;
+use block_cipher_trait::generic_array::GenericArray;
 use block_modes::BlockMode;
+use chrono::Local;
+use zeroize::Zeroize;
 
 use errors::Error;
-use key::Passwords;
-use utils::{self, Aes256Cbc};
+use key::{GroupKey, Passwords};
+use otp::{self, OtpAuth, OtpParams};
+use password::PasswordArgs;
+use secure::{SecretBuf, SecretString};
+use url_match::UrlMatch;
+use utils;
+
+/// Length of per-secret data key
+const DATA_KEY_LEN: usize = 32;
+
+/// Length of data nonce
+const DATA_NONCE_LEN: usize = 12;
+
+/// Max number of previous values kept in history
+pub const HISTORY_LEN: usize = 10;
+
+/// Scheme of TOTP uris
+const OTPAUTH_SCHEME: &str = "otpauth://";
 
 #[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum SecretType {
     Text,
     File,
+    /// Base32 seed of time-based one-time passwords
+    Totp,
 }
 
 /// Secret value
+///
+/// Data key of secret wrapped with cipher of keys group.
+/// Legacy secrets (without data) keep encrypted value here.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SecretValue {
     pub group: String,
     pub value: Vec<u8>,
 }
 
+/// Previous value of secret
+///
+/// Value is encrypted with data key of secret
+/// (or kept plain in plain secret).
+/// Date is the time when value was replaced.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    pub value: Vec<u8>,
+    pub date: i64,
+}
+
+/// Decrypted previous value of secret
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryValue {
+    pub value: SecretBuf,
+    pub date: i64,
+}
+
+/// Kind of custom field
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldKind {
+    Public,
+    Encrypted,
+}
+
+impl Default for FieldKind {
+    fn default() -> FieldKind {
+        FieldKind::Encrypted
+    }
+}
+
+/// Custom field of secret
+///
+/// Encrypted value is encrypted with data key of secret
+/// (or kept plain in plain secret).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Field {
+    pub name: String,
+    pub kind: FieldKind,
+    pub value: Vec<u8>,
+}
+
+/// Custom field in arguments
+#[derive(Debug, Clone, Deserialize)]
+pub struct FieldArgs {
+    pub name: String,
+    #[serde(default)]
+    pub kind: FieldKind,
+    pub value: SecretString,
+}
+
+/// Custom field info for lists output (without encrypted values)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FieldInfo {
+    pub name: String,
+    pub kind: FieldKind,
+    pub value: Option<String>,
+}
+
+/// Custom field with decrypted value
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldValue {
+    pub name: String,
+    pub kind: FieldKind,
+    pub value: SecretBuf,
+}
+
 /// Arguments for adding new secret
 #[derive(Debug, Deserialize)]
 pub struct AddSecretArgs {
     #[serde(rename = "type")]
     pub secret_type: SecretType,
     pub name: String,
-    pub value: String,
+    /// Provided value, either it or generate is required
+    pub value: Option<SecretString>,
+    /// Generate value instead of provided one
+    #[serde(default)]
+    pub generate: Option<PasswordArgs>,
+    /// Add value even if it's found in breaches dataset
+    #[serde(default)]
+    pub allow_breached: bool,
+    /// Rotation period in days
+    pub expiry_days: Option<u32>,
+    pub url: Option<String>,
+    pub login: Option<String>,
+    #[serde(default)]
+    pub url_match: UrlMatch,
+    /// Params of TOTP seed (otpauth uri value has its own)
+    #[serde(default)]
+    pub otp: Option<OtpParams>,
+    #[serde(default)]
+    pub fields: Vec<FieldArgs>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub passwords: Passwords,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Arguments for updating secret found by query.
+/// Empty url, login or folder removes it.
+#[derive(Debug, Deserialize)]
+pub struct UpdateSecretArgs {
+    pub query: Vec<String>,
+    pub name: Option<String>,
+    pub value: Option<SecretString>,
     pub url: Option<String>,
     pub login: Option<String>,
+    pub url_match: Option<UrlMatch>,
+    #[serde(default)]
+    pub set_fields: Vec<FieldArgs>,
+    #[serde(default)]
+    pub remove_fields: Vec<String>,
+    pub tags: Option<Vec<String>>,
+    pub folder: Option<String>,
+    /// Rotation period in days, 0 removes it
+    pub expiry_days: Option<u32>,
+    #[serde(default)]
     pub passwords: Passwords,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Filter of secrets list.
+/// Secret should have all tags, be in folder or its subfolders
+/// and match query (see query::Query).
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SecretsFilter {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub folder: Option<String>,
+    pub query: Option<String>,
+}
+
+impl SecretsFilter {
+    /// Check if secret passes filter
+    pub fn matches(&self, secret: &Secret) -> bool {
+        if !self.tags.iter().all(|t| secret.tags.contains(t)) {
+            return false;
+        }
+        match self.folder {
+            Some(ref f) => match normalize_folder(f) {
+                Some(f) => secret.in_folder(&f),
+                None => true,
+            },
+            None => true,
+        }
+    }
 }
 
 /// Secret info for lists output
@@ -44,7 +209,14 @@ pub struct SecretInfo {
     pub name: String,
     pub url: Option<String>,
     pub login: Option<String>,
+    pub url_match: UrlMatch,
+    pub otp: Option<OtpParams>,
+    pub expiry_days: Option<u32>,
     pub date: i64,
+    pub modified: Option<i64>,
+    pub fields: Vec<FieldInfo>,
+    pub tags: Vec<String>,
+    pub folder: Option<String>,
 }
 
 /// Pasd secret struct
@@ -59,17 +231,40 @@ pub struct Secret {
     value: Option<Vec<u8>>,
     pub values: Vec<SecretValue>,
     pub date: i64,
+    /// Value encrypted with data key [nonce(12), ciphertext]
+    #[serde(default)]
+    pub data: Option<Vec<u8>>,
+    #[serde(default)]
+    pub modified: Option<i64>,
+    #[serde(default)]
+    pub history: Vec<HistoryEntry>,
+    #[serde(default)]
+    pub fields: Vec<Field>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Folder path, e.g. "work/servers"
+    #[serde(default)]
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub url_match: UrlMatch,
+    /// Params of TOTP secret
+    #[serde(default)]
+    pub otp: Option<OtpParams>,
+    /// Rotation period in days
+    #[serde(default)]
+    pub expiry_days: Option<u32>,
 }
 
 impl Secret {
-    /// Create new secret using provided cipher
+    /// Create new secret using provided group keys
+    /// (stored plain without them)
     pub fn new(
         name: String,
         secret_type: SecretType,
         value: &[u8],
         url: Option<String>,
         login: Option<String>,
-        ciphers: HashMap<String, Aes256Cbc>,
+        group_keys: &HashMap<String, GroupKey>,
     ) -> Result<Self, Error> {
         // Read file content
         let value = match secret_type {
@@ -77,123 +272,673 @@ impl Secret {
                 let mut file = File::open(String::from_utf8_lossy(value).into_owned())?;
                 let mut file_value = Vec::with_capacity(2048);
                 file.read_to_end(&mut file_value)?;
-                file_value
+                SecretBuf::new(file_value)
             },
-            SecretType::Text => Vec::from(value),
+            SecretType::Text => SecretBuf::new(Vec::from(value)),
+            SecretType::Totp => otp::normalize_seed(value)?,
         };
 
-        let mut values = Vec::with_capacity(3);
-        for (g, cipher) in ciphers {
-            values.push(SecretValue {
-                group: g.clone(),
-                value: cipher.encrypt_vec(&value.clone()),
-            });
-        }
-
-        Ok(Secret {
+        let mut secret = Secret {
             id: utils::str_id_32(16),
             secret_type: secret_type,
             name: name,
             url: url,
             login: login,
             value: None,
-            values: values,
+            values: vec![],
             date: Local::now().timestamp(),
-        })
+            data: None,
+            modified: None,
+            history: vec![],
+            fields: vec![],
+            tags: vec![],
+            folder: None,
+            url_match: UrlMatch::default(),
+            otp: None,
+            expiry_days: None,
+        };
+        if secret_type == SecretType::Totp {
+            secret.otp = Some(OtpParams::default());
+        }
+        match group_keys.is_empty() {
+            true => secret.set_plain(value.to_vec(), None)?,
+            false => secret.encrypt(&value, None, group_keys)?,
+        }
+
+        Ok(secret)
     }
 
     /// Create secret from arguments object
     pub fn from_args(
         args: AddSecretArgs,
-        ciphers: Option<HashMap<String, Aes256Cbc>>,
+        group_keys: Option<&HashMap<String, GroupKey>>,
     ) -> Result<Self, Error> {
         // Get provided value of secret
-        let value = match args.secret_type {
-            // Read file content
-            SecretType::File => {
-                let mut file = File::open(args.value)?;
-                let mut file_value = Vec::with_capacity(2048);
-                file.read_to_end(&mut file_value)?;
-                file_value
-            },
-            // or just use text value
-            SecretType::Text => Vec::from(args.value),
+        let (value, otp_auth) = {
+            let provided = match args.value {
+                Some(ref v) => v,
+                None => return Err(Error::IncorrectReq),
+            };
+            match args.secret_type {
+                SecretType::Totp => {
+                    let otp_auth = Secret::read_totp(provided, args.otp)?;
+                    (otp_auth.seed.clone(), Some(otp_auth))
+                }
+                _ => (Secret::read_value(args.secret_type, provided)?, None),
+            }
         };
 
-        let mut plain = None;
-        let mut values = Vec::with_capacity(3);
-        if let Some(ciphers) = ciphers {
-            for (g, cipher) in ciphers {
-                values.push(SecretValue {
-                    group: g.clone(),
-                    value: cipher.encrypt_vec(&value.clone()),
-                });
+        // Imported uri may provide name and login
+        let (mut name, mut login) = (args.name, args.login);
+        if let Some(ref otp_auth) = otp_auth {
+            if name.is_empty() {
+                name = otp_auth.issuer.clone().unwrap_or_default();
+            }
+            if login.is_none() {
+                login = otp_auth.account.clone();
             }
-        } else {
-            plain = Some(value);
         }
 
-        let secret = Secret {
+        let mut secret = Secret {
             id: utils::str_id_32(16),
             secret_type: args.secret_type,
-            name: args.name,
+            name: name,
             url: args.url,
-            login: args.login,
-            value: plain,
-            values: values,
+            login: login,
+            value: None,
+            values: vec![],
             date: Local::now().timestamp(),
+            data: None,
+            modified: None,
+            history: vec![],
+            fields: vec![],
+            tags: vec![],
+            folder: None,
+            url_match: UrlMatch::default(),
+            otp: None,
+            expiry_days: None,
         };
 
+        // Fields are plain until value is encrypted
+        secret.set_fields(args.fields, None)?;
+        secret.set_tags(args.tags);
+        secret.url_match = args.url_match;
+        secret.otp = otp_auth.map(|a| a.params);
+        secret.expiry_days = args.expiry_days.filter(|d| *d > 0);
+        secret.folder = args.folder.and_then(|f| normalize_folder(&f));
+
+        match group_keys {
+            Some(group_keys) => secret.encrypt(&value, None, group_keys)?,
+            None => secret.set_plain(value.to_vec(), None)?,
+        }
+
         Ok(secret)
     }
 
-    /// Get decrypted value of secret
-    pub fn decrypt(&self, group_name: String, cipher: Aes256Cbc) -> Result<Vec<u8>, Error> {
-        let value = match self.values.iter().find(|v| v.group == group_name) {
-            Some(v) => v,
-            None => return Err(Error::InvalidKey),
+    /// Update fields of secret. New value is encrypted
+    /// with fresh data key (or stored plain without group keys),
+    /// previous one goes to history.
+    pub fn update(
+        &mut self,
+        args: UpdateSecretArgs,
+        group_keys: Option<&HashMap<String, GroupKey>>,
+    ) -> Result<(), Error> {
+        // Legacy secret gets its own data key
+        if let Some(group_keys) = group_keys {
+            if self.data.is_none() && !self.values.is_empty() {
+                let value = match self.find_unwrap_key(group_keys) {
+                    Some((group_name, group_key)) => self.decrypt(group_name, group_key)?,
+                    None => return Err(Error::InvalidKey),
+                };
+                self.encrypt(&value, None, group_keys)?;
+            }
+        }
+
+        if let Some(name) = args.name {
+            self.name = name;
+        }
+        if let Some(url) = args.url {
+            self.url = if url.is_empty() { None } else { Some(url) };
+        }
+        if let Some(login) = args.login {
+            self.login = if login.is_empty() { None } else { Some(login) };
+        }
+        if let Some(url_match) = args.url_match {
+            self.url_match = url_match;
+        }
+        if let Some(tags) = args.tags {
+            self.set_tags(tags);
+        }
+        if let Some(folder) = args.folder {
+            self.folder = normalize_folder(&folder);
+        }
+        if let Some(expiry_days) = args.expiry_days {
+            self.expiry_days = if expiry_days == 0 { None } else { Some(expiry_days) };
+        }
+
+        // Custom fields
+        for name in args.remove_fields.iter() {
+            self.remove_field(name);
+        }
+        if !args.set_fields.is_empty() {
+            // Data key is needed only for encrypted fields
+            let data_key = if has_encrypted(&args.set_fields) {
+                let unwrap_key = group_keys.and_then(|g| self.find_unwrap_key(g));
+                self.data_key(unwrap_key)?
+            } else {
+                None
+            };
+            self.set_fields(args.set_fields, data_key.as_ref().map(|k| &k[..]))?;
+        }
+
+        // Replace value
+        if let Some(ref value) = args.value {
+            match self.secret_type {
+                SecretType::Totp => {
+                    let otp_auth = Secret::read_totp(value, self.otp)?;
+                    self.replace_value(otp_auth.seed, None, group_keys)?;
+                    self.otp = Some(otp_auth.params);
+                }
+                _ => {
+                    let value = Secret::read_value(self.secret_type, value)?;
+                    self.replace_value(value, None, group_keys)?;
+                }
+            }
+        }
+
+        self.modified = Some(Local::now().timestamp());
+
+        Ok(())
+    }
+
+    /// Get time when current value was set
+    pub fn value_date(&self) -> i64 {
+        match self.history.first() {
+            Some(entry) => entry.date,
+            None => self.date,
+        }
+    }
+
+    /// Check if secret is in folder or in its subfolders
+    pub fn in_folder(&self, folder: &str) -> bool {
+        match self.folder {
+            Some(ref f) => f == folder || f.starts_with(&format!("{}/", folder)),
+            None => false,
+        }
+    }
+
+    /// Move secret from folder (or its subfolder) to another
+    /// one keeping nested path. Return true if secret was moved.
+    pub fn move_folder(&mut self, from: &str, to: &str) -> bool {
+        if !self.in_folder(from) {
+            return false;
+        }
+
+        let nested = match self.folder {
+            Some(ref f) => f[from.len()..].to_string(),
+            None => return false,
         };
+        self.folder = normalize_folder(&format!("{}{}", to, nested));
 
-        Ok(cipher.decrypt_vec(&value.value.clone())?)
+        true
     }
 
-    /// Encrypt new secret value
-    pub fn encrypt(
+    /// Rename tag. Return true if secret had this tag.
+    pub fn rename_tag(&mut self, from: &str, to: &str) -> bool {
+        if !self.tags.iter().any(|t| t == from) {
+            return false;
+        }
+
+        let tags = self
+            .tags
+            .iter()
+            .map(|t| if t == from { to.to_string() } else { t.clone() })
+            .collect();
+        self.set_tags(tags);
+
+        true
+    }
+
+    /// Set tags without empty and duplicated ones
+    fn set_tags(&mut self, tags: Vec<String>) {
+        self.tags.clear();
+        for tag in tags {
+            let tag = tag.trim().to_string();
+            if !tag.is_empty() && !self.tags.contains(&tag) {
+                self.tags.push(tag);
+            }
+        }
+    }
+
+    /// Make value from history the current one
+    pub fn restore_history(
         &mut self,
-        value: &Vec<u8>,
-        ciphers: HashMap<String, Aes256Cbc>,
+        index: usize,
+        group_keys: Option<&HashMap<String, GroupKey>>,
     ) -> Result<(), Error> {
-        let mut values = Vec::with_capacity(3);
+        if index >= self.history.len() {
+            return Err(Error::NotFound);
+        }
+
+        let value = {
+            let unwrap_key = group_keys.and_then(|g| self.find_unwrap_key(g));
+            self.history(unwrap_key)?.remove(index).value
+        };
+        self.replace_value(value, Some(index), group_keys)?;
+        self.modified = Some(Local::now().timestamp());
+
+        Ok(())
+    }
 
-        // Remove plain value
-        if self.value.is_some() {
-            self.value = None
+    /// Get decrypted previous values, newest first.
+    /// Group key is not needed for plain secret.
+    pub fn history(&self, unwrap_key: Option<(&str, &GroupKey)>) -> Result<Vec<HistoryValue>, Error> {
+        if self.history.is_empty() {
+            return Ok(vec![]);
         }
 
-        for (g, cipher) in ciphers {
-            values.push(SecretValue {
-                group: g.clone(),
-                value: cipher.encrypt_vec(&value.clone()),
+        let data_key = self.data_key(unwrap_key)?;
+        let data_key = data_key.as_ref().map(|k| &k[..]);
+
+        let mut history = Vec::with_capacity(self.history.len());
+        for entry in self.history.iter() {
+            history.push(HistoryValue {
+                value: decrypt_with(data_key, &entry.value)?,
+                date: entry.date,
             });
         }
 
-        self.values = values;
+        Ok(history)
+    }
+
+    /// Get custom fields with decrypted values.
+    /// Group key is not needed if there are no encrypted fields.
+    pub fn fields(&self, unwrap_key: Option<(&str, &GroupKey)>) -> Result<Vec<FieldValue>, Error> {
+        let data_key = match self.fields.iter().any(|f| f.kind == FieldKind::Encrypted) {
+            true => self.data_key(unwrap_key)?,
+            false => None,
+        };
+        let data_key = data_key.as_ref().map(|k| &k[..]);
+
+        let mut fields = Vec::with_capacity(self.fields.len());
+        for field in self.fields.iter() {
+            let value = match field.kind {
+                FieldKind::Public => SecretBuf::new(field.value.clone()),
+                FieldKind::Encrypted => decrypt_with(data_key, &field.value)?,
+            };
+            fields.push(FieldValue {
+                name: field.name.clone(),
+                kind: field.kind,
+                value: value,
+            });
+        }
+
+        Ok(fields)
+    }
+
+    /// Get decrypted value of secret
+    pub fn decrypt(&self, group_name: &str, group_key: &GroupKey) -> Result<SecretBuf, Error> {
+        let unwrapped = self.unwrap_value(group_name, group_key)?;
+
+        // Legacy secret keeps value itself
+        let data = match self.data {
+            Some(ref d) => d,
+            None => return Ok(unwrapped),
+        };
+
+        decrypt_data(&unwrapped, data)
+    }
+
+    /// Encrypt new secret value with fresh data key and wrap
+    /// it for every keys group. History and encrypted fields
+    /// are moved from provided current data key (None if plain).
+    pub fn encrypt(
+        &mut self,
+        value: &[u8],
+        data_key: Option<&[u8]>,
+        group_keys: &HashMap<String, GroupKey>,
+    ) -> Result<(), Error> {
+        // Data key wrapped for nobody could never be recovered
+        if group_keys.is_empty() {
+            return Err(Error::NotEnoughKeys);
+        }
+        let new_data_key = SecretBuf::new(utils::rand_bytes(DATA_KEY_LEN));
+
+        // Encrypt value, previous values and fields
+        let data = encrypt_data(&new_data_key, value)?;
+        self.recrypt_extras(data_key, Some(&new_data_key[..]))?;
+
+        self.values = Secret::wrap_data_key(&new_data_key, group_keys)?;
+        self.data = Some(data);
+        self.value = None;
 
         Ok(())
     }
 
+    /// Get copy of secret with data key wrapped for
+    /// new set of keys groups. Provided group key is used
+    /// for unwrapping current data key (None for plain secret).
+    pub fn rewrapped(
+        &self,
+        unwrap_key: Option<(&str, &GroupKey)>,
+        group_keys: &HashMap<String, GroupKey>,
+    ) -> Result<Secret, Error> {
+        let mut secret = self.clone();
+
+        match (unwrap_key, &self.data) {
+            // Only data key should be rewrapped
+            (Some((group_name, group_key)), &Some(_)) if !group_keys.is_empty() => {
+                let data_key = self.unwrap_value(group_name, group_key)?;
+                secret.values = Secret::wrap_data_key(&data_key, group_keys)?;
+            }
+            // Encrypted secret without keys left becomes plain
+            (Some((group_name, group_key)), _) if group_keys.is_empty() => {
+                let data_key = self.data_key(unwrap_key)?;
+                let value = self.decrypt(group_name, group_key)?;
+                secret.set_plain(value.to_vec(), data_key.as_ref().map(|k| &k[..]))?;
+            }
+            // Legacy secret gets its own data key
+            (Some((group_name, group_key)), &None) => {
+                let value = self.decrypt(group_name, group_key)?;
+                secret.encrypt(&value, None, group_keys)?;
+            }
+            // Plain secret
+            (None, _) if !group_keys.is_empty() => {
+                let value = self.get_plain()?;
+                secret.encrypt(&value, None, group_keys)?;
+            }
+            _ => (),
+        }
+
+        Ok(secret)
+    }
+
     /// Try to get plain value
-    pub fn get_plain(&self) -> Result<Vec<u8>, Error> {
+    pub fn get_plain(&self) -> Result<SecretBuf, Error> {
         match self.value {
-            Some(ref v) => return Ok(v.clone()),
+            Some(ref v) => return Ok(SecretBuf::new(v.clone())),
             None => return Err(Error::NotFound),
         }
     }
 
-    /// Set plain value
-    pub fn set_plain(&mut self, value: Vec<u8>) {
+    /// Set plain value. History and encrypted fields are
+    /// decrypted with provided current data key (None if plain).
+    pub fn set_plain(&mut self, value: Vec<u8>, data_key: Option<&[u8]>) -> Result<(), Error> {
+        self.recrypt_extras(data_key, None)?;
+        if let Some(ref mut v) = self.value {
+            v.zeroize();
+        }
         self.value = Some(value);
         self.values = vec![];
+        self.data = None;
+
+        Ok(())
+    }
+
+    /// Get data key of secret (None for plain and legacy secrets)
+    fn data_key(&self, unwrap_key: Option<(&str, &GroupKey)>) -> Result<Option<SecretBuf>, Error> {
+        match (&self.data, unwrap_key) {
+            (&Some(_), Some((group_name, group_key))) => {
+                Ok(Some(self.unwrap_value(group_name, group_key)?))
+            }
+            (&Some(_), None) => Err(Error::InvalidKey),
+            (&None, _) => Ok(None),
+        }
+    }
+
+    /// Move history and encrypted fields from one data key
+    /// to another (None means plain)
+    fn recrypt_extras(&mut self, from: Option<&[u8]>, to: Option<&[u8]>) -> Result<(), Error> {
+        let mut history = Vec::with_capacity(self.history.len());
+        for entry in self.history.iter() {
+            let value = decrypt_with(from, &entry.value)?;
+            history.push(HistoryEntry {
+                value: encrypt_with(to, &value)?,
+                date: entry.date,
+            });
+        }
+
+        let mut fields = Vec::with_capacity(self.fields.len());
+        for field in self.fields.iter() {
+            let value = match field.kind {
+                FieldKind::Public => field.value.clone(),
+                FieldKind::Encrypted => encrypt_with(to, &decrypt_with(from, &field.value)?)?,
+            };
+            fields.push(Field {
+                name: field.name.clone(),
+                kind: field.kind,
+                value: value,
+            });
+        }
+
+        // Replace only when everything is moved
+        self.zero_extras();
+        self.history = history;
+        self.fields = fields;
+
+        Ok(())
+    }
+
+    /// Add or replace custom fields. Encrypted values are
+    /// encrypted with provided data key (None if plain).
+    fn set_fields(&mut self, fields: Vec<FieldArgs>, data_key: Option<&[u8]>) -> Result<(), Error> {
+        for field in fields {
+            let value = match field.kind {
+                FieldKind::Public => Vec::from(field.value.as_bytes()),
+                FieldKind::Encrypted => encrypt_with(data_key, field.value.as_bytes())?,
+            };
+            self.remove_field(&field.name);
+            self.fields.push(Field {
+                name: field.name,
+                kind: field.kind,
+                value: value,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Remove custom field by name
+    fn remove_field(&mut self, name: &str) {
+        if let Some(i) = self.fields.iter().position(|f| f.name == name) {
+            let mut field = self.fields.remove(i);
+            field.value.zeroize();
+        }
+    }
+
+    /// Replace current value and move it to the top of history.
+    /// Entry with provided index is removed from history.
+    fn replace_value(
+        &mut self,
+        value: SecretBuf,
+        restored: Option<usize>,
+        group_keys: Option<&HashMap<String, GroupKey>>,
+    ) -> Result<(), Error> {
+        let (current, data_key) = {
+            let unwrap_key = group_keys.and_then(|g| self.find_unwrap_key(g));
+            let current = match unwrap_key {
+                Some((group_name, group_key)) => self.decrypt(group_name, group_key)?,
+                None => self.get_plain()?,
+            };
+            (current, self.data_key(unwrap_key)?)
+        };
+        let data_key = data_key.as_ref().map(|k| &k[..]);
+
+        // Update history
+        let entry = HistoryEntry {
+            value: encrypt_with(data_key, &current)?,
+            date: Local::now().timestamp(),
+        };
+        if let Some(i) = restored {
+            let mut restored_entry = self.history.remove(i);
+            restored_entry.value.zeroize();
+        }
+        self.history.insert(0, entry);
+        while self.history.len() > HISTORY_LEN {
+            if let Some(mut old_entry) = self.history.pop() {
+                old_entry.value.zeroize();
+            }
+        }
+
+        match group_keys {
+            Some(group_keys) => self.encrypt(&value, data_key, group_keys),
+            None => self.set_plain(value.to_vec(), data_key),
+        }
+    }
+
+    /// Zero history and fields values
+    fn zero_extras(&mut self) {
+        for entry in self.history.iter_mut() {
+            entry.value.zeroize();
+        }
+        for field in self.fields.iter_mut() {
+            field.value.zeroize();
+        }
+    }
+
+    /// Find group key that can unwrap data key of secret
+    fn find_unwrap_key<'a>(
+        &'a self,
+        group_keys: &'a HashMap<String, GroupKey>,
+    ) -> Option<(&'a str, &'a GroupKey)> {
+        self.values
+            .iter()
+            .filter_map(|v| group_keys.get(&v.group).map(|k| (v.group.as_str(), k)))
+            .next()
+    }
+
+    /// Get value of secret from provided text or file path
+    fn read_value(secret_type: SecretType, value: &SecretString) -> Result<SecretBuf, Error> {
+        match secret_type {
+            // Read file content
+            SecretType::File => {
+                let mut file = File::open(value.as_str())?;
+                let mut file_value = Vec::with_capacity(2048);
+                file.read_to_end(&mut file_value)?;
+                Ok(SecretBuf::new(file_value))
+            },
+            // or just use text value
+            SecretType::Text => Ok(SecretBuf::new(Vec::from(value.as_bytes()))),
+            SecretType::Totp => Ok(Secret::read_totp(value, None)?.seed),
+        }
+    }
+
+    /// Get TOTP seed from otpauth uri or base32 value
+    /// with provided (or default) params
+    fn read_totp(value: &SecretString, params: Option<OtpParams>) -> Result<OtpAuth, Error> {
+        if value.as_str().starts_with(OTPAUTH_SCHEME) {
+            return otp::parse_uri(value.as_str());
+        }
+
+        let params = params.unwrap_or_default();
+        params.validate()?;
+        Ok(OtpAuth {
+            seed: otp::normalize_seed(value.as_bytes())?,
+            params: params,
+            account: None,
+            issuer: None,
+        })
+    }
+
+    /// Decrypt value of provided group
+    fn unwrap_value(&self, group_name: &str, group_key: &GroupKey) -> Result<SecretBuf, Error> {
+        let value = match self.values.iter().find(|v| v.group == group_name) {
+            Some(v) => v,
+            None => return Err(Error::InvalidKey),
+        };
+
+        Ok(SecretBuf::new(group_key.cipher()?.decrypt_vec(&value.value)?))
+    }
+
+    /// Wrap data key for every keys group
+    fn wrap_data_key(
+        data_key: &[u8],
+        group_keys: &HashMap<String, GroupKey>,
+    ) -> Result<Vec<SecretValue>, Error> {
+        let mut values = Vec::with_capacity(group_keys.len());
+        for (g, group_key) in group_keys.iter() {
+            values.push(SecretValue {
+                group: g.clone(),
+                value: group_key.cipher()?.encrypt_vec(data_key),
+            });
+        }
+
+        Ok(values)
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        // Zero plain value, history and fields
+        if let Some(ref mut v) = self.value {
+            v.zeroize();
+        }
+        self.zero_extras();
+    }
+}
+
+/// Encrypt value with data key [nonce(12), ciphertext]
+fn encrypt_data(data_key: &[u8], value: &[u8]) -> Result<Vec<u8>, Error> {
+    let nonce = utils::rand_bytes(DATA_NONCE_LEN);
+    let cipher = utils::gcm_cipher(data_key);
+    let mut data = nonce.clone();
+    match cipher.encrypt(GenericArray::from_slice(&nonce), value) {
+        Ok(mut e) => data.append(&mut e),
+        Err(_) => return Err(Error::Internal),
+    }
+
+    Ok(data)
+}
+
+/// Get folder path without empty segments,
+/// None for root folder
+pub fn normalize_folder(path: &str) -> Option<String> {
+    let segments: Vec<&str> = path
+        .split('/')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    match segments.len() {
+        0 => None,
+        _ => Some(segments.join("/")),
+    }
+}
+
+/// Check if any of custom fields should be encrypted
+pub fn has_encrypted(fields: &[FieldArgs]) -> bool {
+    fields.iter().any(|f| f.kind == FieldKind::Encrypted)
+}
+
+/// Encrypt value with data key or keep it plain
+fn encrypt_with(data_key: Option<&[u8]>, value: &[u8]) -> Result<Vec<u8>, Error> {
+    match data_key {
+        Some(k) => encrypt_data(k, value),
+        None => Ok(Vec::from(value)),
+    }
+}
+
+/// Decrypt value with data key or take plain one
+fn decrypt_with(data_key: Option<&[u8]>, data: &[u8]) -> Result<SecretBuf, Error> {
+    match data_key {
+        Some(k) => decrypt_data(k, data),
+        None => Ok(SecretBuf::new(Vec::from(data))),
+    }
+}
+
+/// Decrypt value encrypted with data key
+fn decrypt_data(data_key: &[u8], data: &[u8]) -> Result<SecretBuf, Error> {
+    if data_key.len() != DATA_KEY_LEN {
+        return Err(Error::InvalidKey);
+    }
+    if data.len() < DATA_NONCE_LEN {
+        return Err(Error::Tampered);
+    }
+    let (nonce, encrypted) = data.split_at(DATA_NONCE_LEN);
+    let cipher = utils::gcm_cipher(data_key);
+    match cipher.decrypt(GenericArray::from_slice(nonce), encrypted) {
+        Ok(v) => Ok(SecretBuf::new(v)),
+        Err(_) => Err(Error::Tampered),
     }
 }
 
@@ -205,30 +950,75 @@ impl<'a> From<&'a Secret> for SecretInfo {
             name: s.name.clone(),
             url: s.url.clone(),
             login: s.login.clone(),
+            url_match: s.url_match,
+            otp: s.otp,
+            expiry_days: s.expiry_days,
             date: s.date,
+            modified: s.modified,
+            tags: s.tags.clone(),
+            folder: s.folder.clone(),
+            fields: s
+                .fields
+                .iter()
+                .map(|f| FieldInfo {
+                    name: f.name.clone(),
+                    kind: f.kind,
+                    value: match f.kind {
+                        FieldKind::Public => Some(String::from_utf8_lossy(&f.value).into_owned()),
+                        FieldKind::Encrypted => None,
+                    },
+                })
+                .collect(),
         }
     }
 }
 
+/// Plain text secret with value "value" for tests
+#[cfg(test)]
+pub fn test_secret(name: &str) -> Secret {
+    let args = AddSecretArgs {
+        secret_type: SecretType::Text,
+        name: name.to_string(),
+        value: Some(SecretString::new("value".to_string())),
+        generate: None,
+        allow_breached: false,
+        expiry_days: None,
+        url: None,
+        login: None,
+        url_match: UrlMatch::default(),
+        otp: None,
+        fields: vec![],
+        tags: vec![],
+        folder: None,
+        passwords: HashMap::new(),
+        token: None,
+    };
+    Secret::from_args(args, None).unwrap()
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
+    use block_cipher_trait::generic_array::GenericArray;
     use block_modes::BlockMode;
+    use key::GroupKey;
     use secret::*;
+    use secure::{SecretBuf, SecretString};
+    use url_match::UrlMatch;
 
-    #[test]
-    fn creating_new_secret() {
-        // define two ciphers
-        let key_1 = [1u8; 32];
-        let cipher_1 = Aes256Cbc::new_var(&key_1, &[1u8; 16]).unwrap();
-
-        let key_2 = [2u8; 32];
-        let cipher_2 = Aes256Cbc::new_var(&key_2, &[2u8; 16]).unwrap();
+    fn test_group_key(n: u8) -> GroupKey {
+        GroupKey::new(SecretBuf::new(vec![n; 32]), *GenericArray::from_slice(&[n; 16]))
+    }
 
-        let mut ciphers = HashMap::with_capacity(2);
-        ciphers.insert("Uno".to_string(), cipher_1);
-        ciphers.insert("Duo".to_string(), cipher_2);
+    fn group_keys() -> HashMap<String, GroupKey> {
+        let mut group_keys = HashMap::with_capacity(2);
+        group_keys.insert("Uno".to_string(), test_group_key(1));
+        group_keys.insert("Duo".to_string(), test_group_key(2));
+        group_keys
+    }
 
+    #[test]
+    fn creating_new_secret() {
         // create new secret
         let secret = Secret::new(
             "Ok, secret".to_string(),
@@ -236,7 +1026,7 @@ mod tests {
             "This is value".as_bytes(),
             None,
             None,
-            ciphers,
+            &group_keys(),
         ).unwrap();
 
         // check fields
@@ -246,9 +1036,269 @@ mod tests {
         assert_eq!(secret.login, None);
         assert_eq!(secret.values.len(), 2);
 
-        let key_1a = [1u8; 32];
-        let cipher_1a = Aes256Cbc::new_var(&key_1a, &[1u8; 16]).unwrap();
-        let value = secret.decrypt("Uno".to_string(), cipher_1a).unwrap();
-        assert_eq!(value, Vec::from("This is value"));
+        let group_key = test_group_key(1);
+        let value = secret.decrypt("Uno", &group_key).unwrap();
+        assert_eq!(value.to_vec(), Vec::from("This is value"));
+    }
+
+    #[test]
+    fn secret_without_keys() {
+        let mut secret = Secret::new(
+            "Plain".to_string(),
+            SecretType::Text,
+            "value".as_bytes(),
+            None,
+            None,
+            &HashMap::new(),
+        ).unwrap();
+
+        // Stored plain instead of unwrappable data key
+        assert!(secret.data.is_none());
+        assert!(secret.values.is_empty());
+        assert_eq!(secret.get_plain().unwrap().to_vec(), Vec::from("value"));
+        assert!(secret.encrypt(b"other", None, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn rewrapping_keeps_data() {
+        let group_keys = group_keys();
+        let secret = Secret::new(
+            "Secret".to_string(),
+            SecretType::Text,
+            "value".as_bytes(),
+            None,
+            None,
+            &group_keys,
+        ).unwrap();
+
+        // Drop one group and add another
+        let mut new_group_keys = HashMap::with_capacity(2);
+        new_group_keys.insert("Duo".to_string(), group_keys["Duo"].clone());
+        new_group_keys.insert("Tres".to_string(), test_group_key(3));
+        let rewrapped = secret
+            .rewrapped(Some(("Uno", &group_keys["Uno"])), &new_group_keys)
+            .unwrap();
+
+        // Value itself is not re-encrypted
+        assert_eq!(rewrapped.data, secret.data);
+        assert_eq!(rewrapped.values.len(), 2);
+        assert!(rewrapped.decrypt("Uno", &group_keys["Uno"]).is_err());
+        let value = rewrapped.decrypt("Tres", &new_group_keys["Tres"]).unwrap();
+        assert_eq!(value.to_vec(), Vec::from("value"));
+
+        // Without keys secret becomes plain
+        let plain = rewrapped
+            .rewrapped(Some(("Duo", &group_keys["Duo"])), &HashMap::new())
+            .unwrap();
+        assert_eq!(plain.get_plain().unwrap().to_vec(), Vec::from("value"));
+    }
+
+    #[test]
+    fn updating_secret() {
+        let group_keys = group_keys();
+        let mut secret = Secret::new(
+            "Secret".to_string(),
+            SecretType::Text,
+            "value".as_bytes(),
+            Some("https://example.com".to_string()),
+            None,
+            &group_keys,
+        ).unwrap();
+        let (id, date, data) = (secret.id.clone(), secret.date, secret.data.clone());
+
+        let args = UpdateSecretArgs {
+            query: vec![],
+            name: Some("Renamed".to_string()),
+            value: Some(SecretString::new("new value".to_string())),
+            url: Some("".to_string()),
+            login: Some("user".to_string()),
+            url_match: None,
+            set_fields: vec![],
+            remove_fields: vec![],
+            tags: None,
+            folder: None,
+            expiry_days: None,
+            passwords: HashMap::new(),
+            token: None,
+        };
+        secret.update(args, Some(&group_keys)).unwrap();
+
+        assert_eq!(secret.id, id);
+        assert_eq!(secret.date, date);
+        assert!(secret.modified.is_some());
+        assert_eq!(secret.name, "Renamed".to_string());
+        assert_eq!(secret.url, None);
+        assert_eq!(secret.login, Some("user".to_string()));
+        assert!(secret.data != data);
+        let value = secret.decrypt("Duo", &group_keys["Duo"]).unwrap();
+        assert_eq!(value.to_vec(), Vec::from("new value"));
+    }
+
+    #[test]
+    fn history_survives_rewrapping() {
+        let group_keys = group_keys();
+        let mut secret = Secret::new(
+            "Secret".to_string(),
+            SecretType::Text,
+            "old".as_bytes(),
+            None,
+            None,
+            &group_keys,
+        ).unwrap();
+        secret
+            .replace_value(SecretBuf::new(Vec::from("new")), None, Some(&group_keys))
+            .unwrap();
+
+        // Encrypted with data key
+        assert!(secret.history[0].value != Vec::from("old"));
+        assert!(secret.history(None).is_err());
+
+        // Plain after removing all keys
+        let plain = secret
+            .rewrapped(Some(("Uno", &group_keys["Uno"])), &HashMap::new())
+            .unwrap();
+        let history = plain.history(None).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].value.to_vec(), Vec::from("old"));
+
+        // And encrypted again
+        let encrypted = plain.rewrapped(None, &group_keys).unwrap();
+        let history = encrypted.history(Some(("Duo", &group_keys["Duo"]))).unwrap();
+        assert_eq!(history[0].value.to_vec(), Vec::from("old"));
+    }
+
+    #[test]
+    fn folders_and_tags() {
+        let mut secret = Secret::new(
+            "Secret".to_string(),
+            SecretType::Text,
+            "value".as_bytes(),
+            None,
+            None,
+            &HashMap::new(),
+        ).unwrap();
+        secret.folder = normalize_folder("/work//servers/ ");
+        secret.set_tags(vec!["ssh".to_string(), "ssh".to_string(), " ".to_string()]);
+
+        assert_eq!(secret.folder, Some("work/servers".to_string()));
+        assert_eq!(secret.tags, vec!["ssh".to_string()]);
+        assert!(secret.in_folder("work"));
+        assert!(!secret.in_folder("wor"));
+
+        // Filter
+        let mut filter = SecretsFilter::default();
+        filter.folder = Some("work/".to_string());
+        filter.tags = vec!["ssh".to_string()];
+        assert!(filter.matches(&secret));
+        filter.tags.push("web".to_string());
+        assert!(!filter.matches(&secret));
+
+        // Bulk changes
+        assert!(secret.move_folder("work", "archive/work"));
+        assert_eq!(secret.folder, Some("archive/work/servers".to_string()));
+        assert!(!secret.move_folder("work", "other"));
+        assert!(secret.rename_tag("ssh", "shell"));
+        assert_eq!(secret.tags, vec!["shell".to_string()]);
+    }
+
+    #[test]
+    fn totp_secret() {
+        let group_keys = group_keys();
+        let args = AddSecretArgs {
+            secret_type: SecretType::Totp,
+            name: "".to_string(),
+            value: Some(SecretString::new(
+                "otpauth://totp/Bank:bob?secret=jbsw%20y3dp&period=60".to_string(),
+            )),
+            url: None,
+            login: None,
+            url_match: UrlMatch::default(),
+            otp: None,
+            generate: None,
+            allow_breached: false,
+            expiry_days: None,
+            fields: vec![],
+            tags: vec![],
+            folder: None,
+            passwords: HashMap::new(),
+            token: None,
+        };
+        let mut secret = Secret::from_args(args, Some(&group_keys)).unwrap();
+
+        assert_eq!(secret.name, "Bank".to_string());
+        assert_eq!(secret.login, Some("bob".to_string()));
+        assert_eq!(secret.otp.unwrap().period, 60);
+        let value = secret.decrypt("Uno", &group_keys["Uno"]).unwrap();
+        assert_eq!(value.to_vec(), Vec::from("JBSWY3DP"));
+
+        // Plain seed keeps params, invalid one is rejected
+        let mut args = UpdateSecretArgs {
+            query: vec![],
+            name: None,
+            value: Some(SecretString::new("MZXW6YQ".to_string())),
+            url: None,
+            login: None,
+            url_match: None,
+            set_fields: vec![],
+            remove_fields: vec![],
+            tags: None,
+            folder: None,
+            expiry_days: None,
+            passwords: HashMap::new(),
+            token: None,
+        };
+        secret.update(args, Some(&group_keys)).unwrap();
+        assert_eq!(secret.otp.unwrap().period, 60);
+        args = UpdateSecretArgs {
+            query: vec![],
+            name: None,
+            value: Some(SecretString::new("not base32!".to_string())),
+            url: None,
+            login: None,
+            url_match: None,
+            set_fields: vec![],
+            remove_fields: vec![],
+            tags: None,
+            folder: None,
+            expiry_days: None,
+            passwords: HashMap::new(),
+            token: None,
+        };
+        assert!(secret.update(args, Some(&group_keys)).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn legacy_secret() {
+        let group_keys = group_keys();
+        let legacy_value = group_keys["Uno"]
+            .cipher()
+            .unwrap()
+            .encrypt_vec("value".as_bytes());
+        let mut secret = Secret::new(
+            "Secret".to_string(),
+            SecretType::Text,
+            "".as_bytes(),
+            None,
+            None,
+            &HashMap::new(),
+        ).unwrap();
+        secret.value = None;
+        secret.data = None;
+        secret.values = vec![SecretValue {
+            group: "Uno".to_string(),
+            value: legacy_value,
+        }];
+
+        // Legacy value is decrypted directly
+        let value = secret.decrypt("Uno", &group_keys["Uno"]).unwrap();
+        assert_eq!(value.to_vec(), Vec::from("value"));
+
+        // And gets data key after rewrapping
+        let rewrapped = secret
+            .rewrapped(Some(("Uno", &group_keys["Uno"])), &group_keys)
+            .unwrap();
+        assert!(rewrapped.data.is_some());
+        let value = rewrapped.decrypt("Duo", &group_keys["Duo"]).unwrap();
+        assert_eq!(value.to_vec(), Vec::from("value"));
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::str;
+
+use region;
+use rlimit::{setrlimit, Resource};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroize;
+
+use errors::Error;
+
+/// Buffer for decrypted values and derived keys.
+/// Its memory is locked (if possible) and zeroed on drop.
+pub struct SecretBuf {
+    data: Vec<u8>,
+    lock: Option<region::LockGuard>,
+}
+
+impl SecretBuf {
+    pub fn new(data: Vec<u8>) -> SecretBuf {
+        let lock = SecretBuf::lock_memory(&data);
+        SecretBuf {
+            data: data,
+            lock: lock,
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> SecretBuf {
+        SecretBuf::new(Vec::with_capacity(capacity))
+    }
+
+    /// Append bytes without leaving copies in reallocated memory
+    pub fn extend_from_slice(&mut self, other: &[u8]) {
+        let len = self.data.len() + other.len();
+        if len > self.data.capacity() {
+            let mut grown = SecretBuf::with_capacity(len * 2);
+            grown.data.extend_from_slice(&self.data);
+            mem::swap(self, &mut grown);
+        }
+        self.data.extend_from_slice(other);
+    }
+
+    /// Try to lock allocated memory of buffer
+    fn lock_memory(data: &Vec<u8>) -> Option<region::LockGuard> {
+        if data.capacity() == 0 {
+            return None;
+        }
+        region::lock(data.as_ptr(), data.capacity()).ok()
+    }
+}
+
+impl Drop for SecretBuf {
+    fn drop(&mut self) {
+        self.data.zeroize();
+    }
+}
+
+impl Clone for SecretBuf {
+    fn clone(&self) -> SecretBuf {
+        let mut buf = SecretBuf::with_capacity(self.data.len());
+        buf.extend_from_slice(&self.data);
+        buf
+    }
+}
+
+impl Write for SecretBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Deref for SecretBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl DerefMut for SecretBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+impl PartialEq for SecretBuf {
+    fn eq(&self, other: &SecretBuf) -> bool {
+        self.data == other.data
+    }
+}
+
+impl fmt::Debug for SecretBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecretBuf(***)")
+    }
+}
+
+impl Serialize for SecretBuf {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.data)
+    }
+}
+
+/// String for passwords and secret values received from clients
+#[derive(Clone, PartialEq)]
+pub struct SecretString(SecretBuf);
+
+impl SecretString {
+    pub fn new(value: String) -> SecretString {
+        SecretString(SecretBuf::new(value.into_bytes()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        str::from_utf8(&self.0).unwrap_or("")
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Default for SecretString {
+    fn default() -> SecretString {
+        SecretString::new(String::new())
+    }
+}
+
+impl AsRef<[u8]> for SecretString {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecretString(***)")
+    }
+}
+
+impl Serialize for SecretString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SecretString, D::Error> {
+        String::deserialize(deserializer).map(SecretString::new)
+    }
+}
+
+/// Disable core dumps of this process
+pub fn disable_core_dumps() -> Result<(), Error> {
+    setrlimit(Resource::CORE, 0, 0)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use serde_json;
+    use secure::*;
+
+    #[test]
+    fn extending_buffer() {
+        let mut buf = SecretBuf::with_capacity(2);
+        buf.extend_from_slice(b"ab");
+        buf.extend_from_slice(b"cdef");
+        buf.write_all(b"gh").unwrap();
+
+        assert_eq!(&buf[..], b"abcdefgh");
+        assert_eq!(buf.clone(), buf);
+    }
+
+    #[test]
+    fn hidden_debug_output() {
+        let buf = SecretBuf::new(Vec::from("secret"));
+        let string = SecretString::new("secret".to_string());
+
+        assert!(!format!("{:?}", buf).contains("secret"));
+        assert!(!format!("{:?}", string).contains("secret"));
+    }
+
+    #[test]
+    fn secret_string_json() {
+        let string: SecretString = serde_json::from_str("\"pass\"").unwrap();
+        assert_eq!(string.as_str(), "pass");
+        assert_eq!(serde_json::to_string(&string).unwrap(), "\"pass\"");
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use header::KdfParams;
+use key::GroupKey;
+use utils;
+
+/// Length of session token (bytes)
+const TOKEN_LEN: usize = 32;
+
+/// Lifetime of sessions
+///
+/// idle - session expires if it's not used for this time
+/// absolute - session expires after this time since unlock
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SessionPolicy {
+    pub idle: Duration,
+    pub absolute: Duration,
+}
+
+impl Default for SessionPolicy {
+    fn default() -> SessionPolicy {
+        SessionPolicy {
+            idle: Duration::from_secs(300),
+            absolute: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl SessionPolicy {
+    fn is_expired(&self, session: &Session, now: Instant) -> bool {
+        now.duration_since(session.last_used) >= self.idle
+            || now.duration_since(session.created) >= self.absolute
+    }
+}
+
+/// Group key derived at unlock
+#[derive(Clone)]
+pub struct CachedKey {
+    /// Ids of keys of group
+    pub keys_ids: Vec<String>,
+    pub kdf: KdfParams,
+    pub key: GroupKey,
+}
+
+impl fmt::Debug for CachedKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CachedKey({})", self.keys_ids.join(","))
+    }
+}
+
+/// Unlocked session. Passwords aren't kept, only
+/// keys derived from them.
+pub struct Session {
+    /// Ids of keys validated at unlock
+    pub keys_ids: Vec<String>,
+    pub keys: Vec<CachedKey>,
+    created: Instant,
+    last_used: Instant,
+}
+
+/// Unlocked sessions by token
+#[derive(Default)]
+pub struct Sessions {
+    pub policy: SessionPolicy,
+    sessions: HashMap<String, Session>,
+    /// Last unlock or use of any session
+    last_used: Option<Instant>,
+}
+
+impl Sessions {
+    pub fn new(policy: SessionPolicy) -> Sessions {
+        Sessions {
+            policy: policy,
+            sessions: HashMap::new(),
+            last_used: None,
+        }
+    }
+
+    /// Start new session and get its token
+    pub fn open(&mut self, keys_ids: Vec<String>, keys: Vec<CachedKey>, now: Instant) -> String {
+        self.prune(now);
+        self.last_used = Some(now);
+
+        let token: String = utils::rand_bytes(TOKEN_LEN)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        self.sessions.insert(
+            token.clone(),
+            Session {
+                keys_ids: keys_ids,
+                keys: keys,
+                created: now,
+                last_used: now,
+            },
+        );
+
+        token
+    }
+
+    /// Get active session and prolong its idle timeout
+    pub fn get(&mut self, token: &str, now: Instant) -> Option<&Session> {
+        self.prune(now);
+
+        match self.sessions.get_mut(token) {
+            Some(session) => {
+                session.last_used = now;
+                self.last_used = Some(now);
+                Some(session)
+            }
+            None => None,
+        }
+    }
+
+    /// Close session, return false if there was no such one
+    pub fn close(&mut self, token: &str) -> bool {
+        self.sessions.remove(token).is_some()
+    }
+
+    /// Close all sessions
+    pub fn clear(&mut self) {
+        self.sessions.clear();
+    }
+
+    /// Get time since last use of any session
+    /// (None if there were no sessions)
+    pub fn idle_for(&self, now: Instant) -> Option<Duration> {
+        self.last_used.map(|t| now.duration_since(t))
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Check if there is any not expired session
+    pub fn is_active(&self, now: Instant) -> bool {
+        self.sessions.values().any(|s| !self.policy.is_expired(s, now))
+    }
+
+    /// Remove expired sessions
+    fn prune(&mut self, now: Instant) {
+        let policy = self.policy;
+        self.sessions.retain(|_, s| !policy.is_expired(s, now));
+    }
+}
+
+impl fmt::Debug for Sessions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Sessions({})", self.sessions.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use session::*;
+
+    #[test]
+    fn timeouts() {
+        let mut sessions = Sessions::new(SessionPolicy {
+            idle: Duration::from_secs(10),
+            absolute: Duration::from_secs(25),
+        });
+        let start = Instant::now();
+        let token = sessions.open(vec![], vec![], start);
+        assert_eq!(token.len(), 64);
+
+        // Usage prolongs idle timeout
+        assert!(sessions.get(&token, start + Duration::from_secs(8)).is_some());
+        assert!(sessions.get(&token, start + Duration::from_secs(16)).is_some());
+        assert!(sessions.get(&token, start + Duration::from_secs(24)).is_some());
+
+        // But not absolute one
+        assert!(sessions.get(&token, start + Duration::from_secs(25)).is_none());
+        assert_eq!(sessions.len(), 0);
+
+        // Idle
+        let token = sessions.open(vec![], vec![], start);
+        assert!(sessions.is_active(start + Duration::from_secs(9)));
+        assert!(!sessions.is_active(start + Duration::from_secs(10)));
+        assert!(sessions.get(&token, start + Duration::from_secs(10)).is_none());
+
+        // Lock
+        let token = sessions.open(vec![], vec![], start);
+        assert!(sessions.close(&token));
+        assert!(!sessions.close(&token));
+        assert!(sessions.get(&token, start).is_none());
+        let idle = sessions.idle_for(start + Duration::from_secs(30));
+        assert_eq!(idle, Some(Duration::from_secs(30)));
+    }
+}
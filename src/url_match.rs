@@ -0,0 +1,338 @@
+use std::collections::HashSet;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+use regex::Regex;
+use url::{idna, Host, Url};
+
+use errors::Error;
+
+/// Default location of Public Suffix List (publicsuffix package)
+pub const DEFAULT_SUFFIX_LIST_PATH: &str = "/usr/share/publicsuffix/public_suffix_list.dat";
+
+/// Rules of Public Suffix List. Without list (or for
+/// suffixes that aren't listed) registrable domain is
+/// unknown and hosts aren't matched by domain.
+#[derive(Debug, Default)]
+pub struct SuffixList {
+    rules: HashSet<String>,
+    /// Parents of wildcard rules (ck for *.ck)
+    wildcards: HashSet<String>,
+    exceptions: HashSet<String>,
+}
+
+/// How url of secret is matched against origin
+///
+/// Domain - same registrable domain (example.co.uk for a.b.example.co.uk),
+/// its public suffix should be in Public Suffix List
+/// Subdomain - same host or any of its subdomains
+/// Host - same host and port
+/// Regex - url of secret is regex for the whole origin url
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UrlMatch {
+    Domain,
+    Subdomain,
+    Host,
+    Regex,
+}
+
+impl Default for UrlMatch {
+    fn default() -> UrlMatch {
+        UrlMatch::Domain
+    }
+}
+
+/// Strength of match, higher is better
+pub const MATCH_DOMAIN: u8 = 1;
+pub const MATCH_SUBDOMAIN: u8 = 2;
+pub const MATCH_REGEX: u8 = 3;
+pub const MATCH_HOST: u8 = 4;
+pub const MATCH_EXACT: u8 = 5;
+
+impl SuffixList {
+    /// Read list file in Public Suffix List format
+    pub fn read(path: &Path) -> Result<SuffixList, Error> {
+        Ok(SuffixList::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Parse list, rules are converted to ascii form of hosts
+    pub fn parse(text: &str) -> SuffixList {
+        let mut list = SuffixList::default();
+        for line in text.lines() {
+            let rule = match line.split_whitespace().next() {
+                Some(r) if !r.starts_with("//") => r,
+                _ => continue,
+            };
+            let (set, domain) = if rule.starts_with('!') {
+                (&mut list.exceptions, &rule[1..])
+            } else if rule.starts_with("*.") {
+                (&mut list.wildcards, &rule[2..])
+            } else {
+                (&mut list.rules, rule)
+            };
+            if let Ok(domain) = idna::domain_to_ascii(domain) {
+                set.insert(domain.to_lowercase());
+            }
+        }
+        list
+    }
+
+    /// Get registrable domain of host: its public suffix with
+    /// one more label. None for IP addresses, public suffixes
+    /// themselves and hosts under suffixes that aren't listed.
+    pub fn registrable_domain(&self, host: &str) -> Option<String> {
+        let host = host.trim_end_matches('.').to_lowercase();
+        if host.parse::<IpAddr>().is_ok() || host.starts_with('[') {
+            return None;
+        }
+        let labels: Vec<&str> = host.split('.').collect();
+        let n = labels.len();
+
+        // Exception rule prevails, its suffix is one label shorter
+        for i in 0..n {
+            if self.exceptions.contains(&labels[i..].join(".")) {
+                return Some(labels[i..].join("."));
+            }
+        }
+
+        // The longest matching rule
+        for i in 0..n {
+            let suffix = labels[i..].join(".");
+            let wildcard = i + 1 < n && self.wildcards.contains(&labels[i + 1..].join("."));
+            if self.rules.contains(&suffix) || wildcard {
+                return match i {
+                    0 => None,
+                    _ => Some(labels[i - 1..].join(".")),
+                };
+            }
+        }
+
+        None
+    }
+}
+
+/// Parse origin url
+pub fn parse(url: &str) -> Result<Url, Error> {
+    match Url::parse(url) {
+        Ok(u) => Ok(u),
+        Err(_) => Err(Error::InvalidUrl),
+    }
+}
+
+/// Match url of secret against origin and get strength of match
+pub fn match_url(
+    secret_url: &str,
+    mode: UrlMatch,
+    origin: &Url,
+    suffixes: &SuffixList,
+) -> Option<u8> {
+    if mode == UrlMatch::Regex {
+        return match Regex::new(secret_url) {
+            Ok(r) if r.is_match(origin.as_str()) => Some(MATCH_REGEX),
+            _ => None,
+        };
+    }
+
+    // Url of secret may be stored without scheme
+    let has_scheme = secret_url.contains("://");
+    let secret = match has_scheme {
+        true => Url::parse(secret_url).ok()?,
+        false => Url::parse(&format!("https://{}", secret_url)).ok()?,
+    };
+
+    // Scheme: https secret is never sent to http origin
+    if has_scheme && !scheme_allowed(secret.scheme(), origin.scheme()) {
+        return None;
+    }
+    if !has_scheme && !is_web(origin.scheme()) {
+        return None;
+    }
+
+    let secret_host = host_str(&secret)?;
+    let origin_host = host_str(origin)?;
+
+    // Port: host mode needs the same port, other
+    // modes check only explicitly provided one
+    let same_port = match secret.port() {
+        Some(p) => origin.port_or_known_default() == Some(p),
+        None => origin.port().is_none(),
+    };
+    if mode == UrlMatch::Host && !same_port {
+        return None;
+    }
+    if secret.port().is_some() && !same_port {
+        return None;
+    }
+
+    // Strength of relation between hosts
+    let strength = if secret_host == origin_host {
+        match same_port && secret.path() != "/" && is_subpath(origin.path(), secret.path()) {
+            true => MATCH_EXACT,
+            false => MATCH_HOST,
+        }
+    } else if is_subdomain(&origin_host, &secret_host) {
+        MATCH_SUBDOMAIN
+    } else {
+        let origin_domain = suffixes.registrable_domain(&origin_host);
+        let secret_domain = suffixes.registrable_domain(&secret_host);
+        match origin_domain.is_some() && origin_domain == secret_domain {
+            true => MATCH_DOMAIN,
+            false => return None,
+        }
+    };
+
+    let allowed = match mode {
+        UrlMatch::Domain => true,
+        UrlMatch::Subdomain => strength >= MATCH_SUBDOMAIN,
+        UrlMatch::Host => strength >= MATCH_HOST,
+        UrlMatch::Regex => false,
+    };
+    match allowed {
+        true => Some(strength),
+        false => None,
+    }
+}
+
+/// Check if host is subdomain of parent
+fn is_subdomain(host: &str, parent: &str) -> bool {
+    host.ends_with(&format!(".{}", parent))
+}
+
+/// Check if path is the same as parent or nested in it
+fn is_subpath(path: &str, parent: &str) -> bool {
+    let parent = parent.trim_end_matches('/');
+    path == parent || path.starts_with(&format!("{}/", parent))
+}
+
+/// Get lowercase host of url
+fn host_str(url: &Url) -> Option<String> {
+    match url.host() {
+        Some(Host::Domain(d)) => Some(d.trim_end_matches('.').to_lowercase()),
+        Some(Host::Ipv4(ip)) => Some(ip.to_string()),
+        Some(Host::Ipv6(ip)) => Some(format!("[{}]", ip)),
+        None => None,
+    }
+}
+
+fn is_web(scheme: &str) -> bool {
+    scheme == "http" || scheme == "https"
+}
+
+/// Secret of http site may be used on https one, but not vice versa
+fn scheme_allowed(secret_scheme: &str, origin_scheme: &str) -> bool {
+    match (secret_scheme, origin_scheme) {
+        ("http", "https") => true,
+        (s, o) => s == o,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url_match::*;
+
+    /// Excerpt of Public Suffix List
+    static LIST: &str = "\
+// ===BEGIN ICANN DOMAINS===
+com
+org
+uk
+co.uk
+pl
+com.pl
+io
+*.ck
+!www.ck
+рф
+// ===BEGIN PRIVATE DOMAINS===
+github.io
+";
+
+    fn strength(secret_url: &str, mode: UrlMatch, origin: &str) -> Option<u8> {
+        match_url(secret_url, mode, &parse(origin).unwrap(), &SuffixList::parse(LIST))
+    }
+
+    fn domain(host: &str) -> Option<String> {
+        SuffixList::parse(LIST).registrable_domain(host)
+    }
+
+    #[test]
+    fn registrable_domains() {
+        assert_eq!(domain("a.b.example.com"), Some("example.com".to_string()));
+        assert_eq!(domain("www.example.co.uk"), Some("example.co.uk".to_string()));
+        assert_eq!(domain("bank.com.pl"), Some("bank.com.pl".to_string()));
+        assert_eq!(domain("user.github.io"), Some("user.github.io".to_string()));
+        assert_eq!(domain("a.b.ck"), Some("a.b.ck".to_string()));
+        assert_eq!(domain("a.www.ck"), Some("www.ck".to_string()));
+        assert_eq!(domain("xn--80ak6aa92e.xn--p1ai"), Some("xn--80ak6aa92e.xn--p1ai".to_string()));
+
+        // Public suffixes, unknown suffixes and addresses
+        assert_eq!(domain("co.uk"), None);
+        assert_eq!(domain("example.unknown"), None);
+        assert_eq!(domain("localhost"), None);
+        assert_eq!(domain("10.0.0.1"), None);
+    }
+
+    #[test]
+    fn matching_modes() {
+        let origin = "https://login.example.com/signin";
+
+        assert_eq!(strength("example.com", UrlMatch::Domain, origin), Some(MATCH_SUBDOMAIN));
+        assert_eq!(strength("www.example.com", UrlMatch::Domain, origin), Some(MATCH_DOMAIN));
+        assert_eq!(strength("www.example.com", UrlMatch::Subdomain, origin), None);
+        assert_eq!(strength("example.com", UrlMatch::Subdomain, origin), Some(MATCH_SUBDOMAIN));
+        assert_eq!(strength("example.com", UrlMatch::Host, origin), None);
+        assert_eq!(strength("login.example.com", UrlMatch::Host, origin), Some(MATCH_HOST));
+        assert_eq!(
+            strength("https://login.example.com/signin", UrlMatch::Host, origin),
+            Some(MATCH_EXACT)
+        );
+        assert_eq!(
+            strength(r"^https://\w+\.example\.com/", UrlMatch::Regex, origin),
+            Some(MATCH_REGEX)
+        );
+        assert_eq!(strength("example.org", UrlMatch::Domain, origin), None);
+        assert_eq!(strength("other.co.uk", UrlMatch::Domain, "https://example.co.uk"), None);
+        assert_eq!(strength("bank.com.pl", UrlMatch::Domain, "https://evil.com.pl"), None);
+        assert_eq!(strength("a.github.io", UrlMatch::Domain, "https://b.github.io"), None);
+
+        // Unknown suffix
+        assert_eq!(strength("a.example.test", UrlMatch::Domain, "https://b.example.test"), None);
+        assert_eq!(
+            strength("example.test", UrlMatch::Domain, "https://b.example.test"),
+            Some(MATCH_SUBDOMAIN)
+        );
+    }
+
+    #[test]
+    fn paths() {
+        let secret_url = "https://example.com/admin";
+        assert_eq!(
+            strength(secret_url, UrlMatch::Host, "https://example.com/admin/users"),
+            Some(MATCH_EXACT)
+        );
+        assert_eq!(
+            strength(secret_url, UrlMatch::Host, "https://example.com/admin"),
+            Some(MATCH_EXACT)
+        );
+        assert_eq!(
+            strength(secret_url, UrlMatch::Host, "https://example.com/administrator"),
+            Some(MATCH_HOST)
+        );
+    }
+
+    #[test]
+    fn schemes_and_ports() {
+        // No downgrade to http
+        assert_eq!(strength("https://example.com", UrlMatch::Host, "http://example.com"), None);
+        assert!(strength("http://example.com", UrlMatch::Host, "https://example.com").is_some());
+        assert_eq!(strength("example.com", UrlMatch::Domain, "ftp://example.com"), None);
+
+        // Explicit port should be the same
+        assert_eq!(strength("example.com:8080", UrlMatch::Domain, "https://example.com"), None);
+        assert!(strength("example.com:8080", UrlMatch::Host, "http://example.com:8080").is_some());
+        assert!(strength("example.com", UrlMatch::Domain, "https://example.com:8443").is_some());
+        assert_eq!(strength("https://example.com", UrlMatch::Host, "https://example.com:8443"), None);
+    }
+}
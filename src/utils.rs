@@ -1,10 +1,19 @@
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use aes::Aes256;
+use aes_gcm::aead::NewAead;
 use block_modes::block_padding::Pkcs7;
 use block_modes::Cbc;
+use block_cipher_trait::generic_array::GenericArray;
 use rand::{OsRng, Rng};
+use zeroize::Zeroize;
 
+use errors::Error;
+
+pub use aes_gcm::Aes256Gcm;
 pub type Aes256Cbc = Cbc<Aes256, Pkcs7>;
 
 static ALPH: [char; 64] = [
@@ -59,17 +68,14 @@ pub fn str_id_32(len: usize) -> String {
     return output;
 }
 
-/// Generate random string.
-pub fn gen_pass(len: usize) -> String {
-    let mut output = String::with_capacity(len);
+/// Generate random bytes
+pub fn rand_bytes(len: usize) -> Vec<u8> {
+    let mut output = vec![0u8; len];
 
     let mut rng = OsRng::new().unwrap();
-    for _ in 0..len {
-        let rd: u8 = rng.gen();
-        output.push(ALPH[(rd & 63) as usize]);
-    }
+    rng.fill(&mut output[..]);
 
-    return output;
+    output
 }
 
 /// Generate numerical id
@@ -86,9 +92,70 @@ pub fn nid() -> u64 {
     ((rd as u64) << 32) | ns as u64
 }
 
+/// Get AES-GCM cipher without leaving copy of key
+pub fn gcm_cipher(key: &[u8]) -> Aes256Gcm {
+    let mut key_arr = *GenericArray::from_slice(key);
+    let cipher = Aes256Gcm::new(key_arr);
+    key_arr.as_mut_slice().zeroize();
+    cipher
+}
+
+/// Get uid of user running this process
+pub fn process_uid() -> Result<u32, Error> {
+    // Owner of procfs entry of process is its user
+    Ok(fs::metadata("/proc/self")?.uid())
+}
+
+/// Write file atomically: write content to temporary file
+/// in the same dir, sync it and rename over the target.
+pub fn write_atomic<F>(path: &Path, write: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut File) -> Result<(), Error>,
+{
+    let dir = match path.parent() {
+        Some(d) if d != Path::new("") => d,
+        _ => Path::new("."),
+    };
+    let file_name = match path.file_name() {
+        Some(n) => n.to_string_lossy().into_owned(),
+        None => return Err(Error::IncorrectConf),
+    };
+    let tmp_path = dir.join(format!(".{}.{}.tmp", file_name, uid()));
+
+    // Write and sync temporary file
+    let mut tmp_file = OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .mode(0o600)
+        .open(&tmp_path)?;
+    let result = match write(&mut tmp_file) {
+        Ok(_) => tmp_file.sync_all().map_err(Error::IO),
+        Err(err) => Err(err),
+    };
+    // Failed cleanup shouldn't hide the cause
+    if let Err(err) = result {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(err);
+    }
+
+    // Replace target and sync dir to persist rename
+    if let Err(err) = fs::rename(&tmp_path, path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(Error::IO(err));
+    }
+    File::open(dir)?.sync_all()?;
+
+    Ok(())
+}
+
 // --- Tests ---
 #[cfg(test)]
 mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::prelude::*;
+
+    use errors::Error;
     use utils;
 
     #[test]
@@ -98,12 +165,44 @@ mod tests {
     }
 
     #[test]
-    fn gen_pass() {
-        assert!(utils::gen_pass(10).len() == 10);
+    fn rand_bytes() {
+        assert!(utils::rand_bytes(16).len() == 16);
+        assert!(utils::rand_bytes(16) != utils::rand_bytes(16));
     }
 
     #[test]
     fn nid() {
         assert!(utils::nid() != utils::nid());
     }
+
+    #[test]
+    fn write_atomic() {
+        let dir = env::temp_dir().join(format!("pasd_test_{}", utils::uid()));
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("file");
+        fs::write(&path, b"old").unwrap();
+
+        // Writer fails in the middle
+        let result = utils::write_atomic(&path, |file| {
+            file.write_all(b"ne")?;
+            Err(Error::IncorrectReq)
+        });
+        match result {
+            Err(Error::IncorrectReq) => (),
+            _ => panic!("original error expected"),
+        }
+
+        // Target is intact and temporary file is removed
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        utils::write_atomic(&path, |file| {
+            file.write_all(b"new")?;
+            Ok(())
+        }).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }